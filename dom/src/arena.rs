@@ -0,0 +1,473 @@
+use crate::attributes::Attributes;
+use crate::doctype::Doctype;
+use crate::html_elements::{HTMLEnum, HTMLNode, SourcePosition};
+use crate::serialize::NodeHtml;
+use crate::tree::TreeCell;
+use std::ops::{Index, IndexMut};
+
+/// A handle to a node of an [Arena].
+/// Handles stay valid for as long as the arena lives, even once their node has been detached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    /// The position of the node in its arena, in creation order
+    pub fn index(self) -> usize {
+        self.0
+    }
+
+    /// The handle of the same node once its arena has been moved to the end of another one
+    pub(crate) fn shifted(self, offset: usize) -> NodeId {
+        NodeId(self.0 + offset)
+    }
+}
+
+/// The name, attributes and source position of an element of an [Arena]
+#[derive(Debug, Clone, Default)]
+pub struct ElementData {
+    pub name: String,
+    pub attributes: Attributes,
+    pub source_position: Option<SourcePosition>,
+}
+
+impl ElementData {
+    /// Finds an attribute by name, ASCII case-insensitively.
+    /// A valueless attribute gives an empty string
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_deref().unwrap_or(""))
+    }
+}
+
+/// What a node of an [Arena] holds, mirroring [HTMLEnum]
+#[derive(Debug, Clone)]
+pub enum NodeData {
+    Element(ElementData),
+    Text(String),
+    Comment(String),
+    Doctype(Doctype),
+}
+
+/// A node of an [Arena], along with its links to its relatives
+#[derive(Debug, Clone)]
+pub struct ArenaNode {
+    /// What this node holds
+    pub data: NodeData,
+
+    parent: Option<NodeId>,
+    previous_sibling: Option<NodeId>,
+    next_sibling: Option<NodeId>,
+    first_child: Option<NodeId>,
+    last_child: Option<NodeId>,
+}
+
+impl ArenaNode {
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+    pub fn previous_sibling(&self) -> Option<NodeId> {
+        self.previous_sibling
+    }
+    pub fn next_sibling(&self) -> Option<NodeId> {
+        self.next_sibling
+    }
+    pub fn first_child(&self) -> Option<NodeId> {
+        self.first_child
+    }
+    pub fn last_child(&self) -> Option<NodeId> {
+        self.last_child
+    }
+
+    /// The element this node holds, if it holds one
+    pub fn element(&self) -> Option<&ElementData> {
+        match &self.data {
+            NodeData::Element(element) => Some(element),
+            _ => None,
+        }
+    }
+
+    /// The tag name of this node, if it is an element
+    pub fn name(&self) -> Option<&str> {
+        self.element().map(|x| x.name.as_str())
+    }
+}
+
+/// A tree of html nodes stored side by side, and linked through [NodeId]s instead of references.
+/// Every [HTMLNode] is a handle to a node of an arena, shared with the nodes it was ever linked to.
+///
+/// Going to a node's parent, siblings or children is a single lookup, and moving a node around only rewires its neighbours.
+/// Nodes are never freed before the arena is: detached nodes simply have no parent anymore
+#[derive(Debug, Clone, Default)]
+pub struct Arena {
+    nodes: Vec<ArenaNode>,
+}
+
+impl Arena {
+    /// Creates an empty arena
+    pub fn new() -> Arena {
+        Arena::default()
+    }
+
+    /// The number of nodes ever created in this arena, detached ones included
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether no node was ever created in this arena
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Creates a detached node
+    pub fn new_node(&mut self, data: NodeData) -> NodeId {
+        self.nodes.push(ArenaNode {
+            data,
+            parent: None,
+            previous_sibling: None,
+            next_sibling: None,
+            first_child: None,
+            last_child: None,
+        });
+
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Creates a detached element, without attributes
+    pub fn new_element(&mut self, name: &str) -> NodeId {
+        self.new_node(NodeData::Element(ElementData {
+            name: name.to_string(),
+            ..Default::default()
+        }))
+    }
+
+    /// Creates a detached text node
+    pub fn new_text(&mut self, text: &str) -> NodeId {
+        self.new_node(NodeData::Text(text.to_string()))
+    }
+
+    /// The node a handle points to.
+    /// Panics if the handle comes from another arena
+    pub fn get(&self, id: NodeId) -> &ArenaNode {
+        &self.nodes[id.0]
+    }
+
+    /// The element a handle points to, if it is one
+    pub fn element(&self, id: NodeId) -> Option<&ElementData> {
+        self.get(id).element()
+    }
+
+    /// The element a handle points to, if it is one, to be edited
+    pub fn element_mut(&mut self, id: NodeId) -> Option<&mut ElementData> {
+        match &mut self[id] {
+            NodeData::Element(element) => Some(element),
+            _ => None,
+        }
+    }
+
+    /// Whether a node is the other one or one of its ancestors
+    pub fn is_inclusive_ancestor(&self, ancestor: NodeId, id: NodeId) -> bool {
+        ancestor == id || self.ancestors(id).any(|x| x == ancestor)
+    }
+
+    /// Appends a node at the end of another one's children, detaching it from its current parent first.
+    /// Panics if the child is the parent or one of its ancestors, which would make a cycle
+    pub fn append_child(&mut self, parent: NodeId, child: NodeId) {
+        self.insert_before(parent, child, None);
+    }
+
+    /// Inserts a node among another one's children, right before `next`, or last without one.
+    /// Detaches it from its current parent first.
+    /// Panics if the child is the parent or one of its ancestors, which would make a cycle,
+    /// or if `next` is the child itself or not a child of the parent
+    pub fn insert_before(&mut self, parent: NodeId, child: NodeId, next: Option<NodeId>) {
+        assert!(
+            !self.is_inclusive_ancestor(child, parent),
+            "A node cannot be appended to itself or to one of its descendants"
+        );
+        assert!(
+            next.is_none_or(|x| x != child && self.get(x).parent == Some(parent)),
+            "A node can only be inserted before another child of its parent"
+        );
+
+        self.detach(child);
+
+        let previous = match next {
+            Some(next) => self.nodes[next.0].previous_sibling,
+            None => self.nodes[parent.0].last_child,
+        };
+
+        match previous {
+            Some(previous) => self.nodes[previous.0].next_sibling = Some(child),
+            None => self.nodes[parent.0].first_child = Some(child),
+        }
+
+        match next {
+            Some(next) => self.nodes[next.0].previous_sibling = Some(child),
+            None => self.nodes[parent.0].last_child = Some(child),
+        }
+
+        let node = &mut self.nodes[child.0];
+        node.parent = Some(parent);
+        node.previous_sibling = previous;
+        node.next_sibling = next;
+    }
+
+    /// Disconnects a node, along with its descendants, from its parent and siblings
+    pub fn detach(&mut self, id: NodeId) {
+        let node = &mut self.nodes[id.0];
+        let (parent, previous, next) = (node.parent, node.previous_sibling, node.next_sibling);
+
+        node.parent = None;
+        node.previous_sibling = None;
+        node.next_sibling = None;
+
+        match previous {
+            Some(previous) => self.nodes[previous.0].next_sibling = next,
+            None => {
+                if let Some(parent) = parent {
+                    self.nodes[parent.0].first_child = next;
+                }
+            }
+        }
+
+        match next {
+            Some(next) => self.nodes[next.0].previous_sibling = previous,
+            None => {
+                if let Some(parent) = parent {
+                    self.nodes[parent.0].last_child = previous;
+                }
+            }
+        }
+    }
+
+    /// Iterates over the children of a node, text and comments included
+    pub fn children(&self, id: NodeId) -> Siblings<'_> {
+        Siblings {
+            arena: self,
+            next: self.get(id).first_child,
+            backwards: false,
+        }
+    }
+
+    /// Iterates over the nodes after this one in its parent, closest first
+    pub fn following_siblings(&self, id: NodeId) -> Siblings<'_> {
+        Siblings {
+            arena: self,
+            next: self.get(id).next_sibling,
+            backwards: false,
+        }
+    }
+
+    /// Iterates over the nodes before this one in its parent, closest first
+    pub fn preceding_siblings(&self, id: NodeId) -> Siblings<'_> {
+        Siblings {
+            arena: self,
+            next: self.get(id).previous_sibling,
+            backwards: true,
+        }
+    }
+
+    /// Iterates over the parents of a node, closest first
+    pub fn ancestors(&self, id: NodeId) -> Ancestors<'_> {
+        Ancestors {
+            arena: self,
+            next: self.get(id).parent,
+        }
+    }
+
+    /// Lazily iterates over the nodes under this one, in document order, text and comments included
+    pub fn descendants(&self, id: NodeId) -> ArenaDescendants<'_> {
+        ArenaDescendants {
+            arena: self,
+            root: id,
+            next: self.get(id).first_child,
+        }
+    }
+
+    /// The concatenated text of every text node under this one, in document order
+    pub fn text_content(&self, id: NodeId) -> String {
+        let mut buf = String::new();
+
+        for node in self.descendants(id) {
+            if let NodeData::Text(text) = &self[node] {
+                buf += text;
+            }
+        }
+
+        buf
+    }
+
+    /// Copies a node and everything under it into this arena, as a detached node
+    pub fn import(&mut self, html: &HTMLEnum) -> NodeId {
+        match html {
+            HTMLEnum::Text(text) => self.new_node(NodeData::Text(text.clone())),
+            HTMLEnum::Comment(comment) => self.new_node(NodeData::Comment(comment.clone())),
+            HTMLEnum::Doctype(doctype) => self.new_node(NodeData::Doctype(doctype.clone())),
+            HTMLEnum::Node(node) => node.read(|tree, id| self.copy_from(&tree.arena, id)),
+        }
+    }
+
+    /// Copies a node and everything under it from another arena into this one, as a detached node
+    pub fn copy_from(&mut self, source: &Arena, id: NodeId) -> NodeId {
+        let copy = self.new_node(source[id].clone());
+
+        for child in source.children(id) {
+            let child = self.copy_from(source, child);
+            self.append_child(copy, child);
+        }
+
+        copy
+    }
+
+    /// Copies a node and everything under it within this arena, as a detached node
+    pub fn duplicate(&mut self, id: NodeId) -> NodeId {
+        let copy = self.new_node(self[id].clone());
+        let children: Vec<NodeId> = self.children(id).collect();
+
+        for child in children {
+            let child = self.duplicate(child);
+            self.append_child(copy, child);
+        }
+
+        copy
+    }
+
+    /// Moves every node of another arena to the end of this one.
+    /// Returns the offset their handles are [shifted](NodeId::shifted) by
+    pub(crate) fn absorb(&mut self, other: Arena) -> usize {
+        let offset = self.nodes.len();
+        let shift = |x: Option<NodeId>| x.map(|x| x.shifted(offset));
+
+        self.nodes
+            .extend(other.nodes.into_iter().map(|node| ArenaNode {
+                data: node.data,
+                parent: shift(node.parent),
+                previous_sibling: shift(node.previous_sibling),
+                next_sibling: shift(node.next_sibling),
+                first_child: shift(node.first_child),
+                last_child: shift(node.last_child),
+            }));
+
+        offset
+    }
+
+    /// Builds an arena out of a list of nodes, giving the handles to each of them
+    pub fn from_nodes(nodes: &[HTMLEnum]) -> (Arena, Vec<NodeId>) {
+        let mut arena = Arena::new();
+        let roots = nodes.iter().map(|x| arena.import(x)).collect();
+
+        (arena, roots)
+    }
+
+    /// Copies a node and everything under it into a new [HTMLNode] tree, of its own
+    pub fn export(&self, id: NodeId) -> HTMLEnum {
+        match &self[id] {
+            NodeData::Text(text) => HTMLEnum::Text(text.clone()),
+            NodeData::Comment(comment) => HTMLEnum::Comment(comment.clone()),
+            NodeData::Doctype(doctype) => HTMLEnum::Doctype(doctype.clone()),
+            NodeData::Element(_) => {
+                let mut arena = Arena::new();
+                let root = arena.copy_from(self, id);
+
+                HTMLEnum::Node(HTMLNode::at(&TreeCell::new(arena), root))
+            }
+        }
+    }
+
+    /// Serializes a node and everything under it as html
+    pub fn html(&self, id: NodeId) -> String {
+        NodeHtml::new(self, id).to_string()
+    }
+}
+
+impl Index<NodeId> for Arena {
+    type Output = NodeData;
+
+    fn index(&self, id: NodeId) -> &NodeData {
+        &self.nodes[id.0].data
+    }
+}
+
+impl IndexMut<NodeId> for Arena {
+    fn index_mut(&mut self, id: NodeId) -> &mut NodeData {
+        &mut self.nodes[id.0].data
+    }
+}
+
+/// An iterator over nodes following each other in the same parent
+pub struct Siblings<'a> {
+    arena: &'a Arena,
+    next: Option<NodeId>,
+    backwards: bool,
+}
+
+impl Iterator for Siblings<'_> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        let id = self.next?;
+        let node = self.arena.get(id);
+
+        self.next = match self.backwards {
+            false => node.next_sibling,
+            true => node.previous_sibling,
+        };
+        Some(id)
+    }
+}
+
+/// An iterator over the parents of a node, closest first
+pub struct Ancestors<'a> {
+    arena: &'a Arena,
+    next: Option<NodeId>,
+}
+
+impl Iterator for Ancestors<'_> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        let id = self.next?;
+        self.next = self.arena.get(id).parent;
+        Some(id)
+    }
+}
+
+/// A depth-first iterator over the nodes under another, in document order.
+/// Walks through the links alone, without keeping a stack
+pub struct ArenaDescendants<'a> {
+    arena: &'a Arena,
+    root: NodeId,
+    next: Option<NodeId>,
+}
+
+impl Iterator for ArenaDescendants<'_> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        let id = self.next?;
+        let node = self.arena.get(id);
+
+        self.next = node.first_child.or_else(|| {
+            // Climb back up until a node has a next sibling, without going past the root
+            let mut current = id;
+
+            loop {
+                if current == self.root {
+                    return None;
+                }
+
+                let current_node = self.arena.get(current);
+
+                if let Some(next) = current_node.next_sibling {
+                    return Some(next);
+                }
+
+                current = current_node.parent?;
+            }
+        });
+
+        Some(id)
+    }
+}
@@ -0,0 +1,91 @@
+use std::slice::Iter;
+
+/// The attributes of an element, in the order they were first set.
+/// Names are compared exactly: the parser lowercases them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Attributes {
+    list: Vec<(String, Option<String>)>,
+}
+
+impl Attributes {
+    pub fn new() -> Attributes {
+        Attributes::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// The value of an attribute.
+    /// None if the attribute is not set, Some(None) if it is set without a value
+    pub fn get(&self, name: &str) -> Option<Option<&str>> {
+        self.list
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_deref())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.list.iter().any(|(key, _)| key == name)
+    }
+
+    /// Sets an attribute. An attribute that is already set keeps its place, with its value replaced.
+    /// Returns the previous value, if the attribute was set
+    pub fn set(&mut self, name: String, value: Option<String>) -> Option<Option<String>> {
+        match self.list.iter_mut().find(|(key, _)| *key == name) {
+            Some((_, current)) => Some(std::mem::replace(current, value)),
+            None => {
+                self.list.push((name, value));
+                None
+            }
+        }
+    }
+
+    /// Adds an attribute, unless it is already set: as in HTML, the first occurrence of an attribute wins.
+    /// Returns whether it was added
+    pub fn add(&mut self, name: String, value: Option<String>) -> bool {
+        if self.contains(&name) {
+            return false;
+        }
+
+        self.list.push((name, value));
+        true
+    }
+
+    /// Removes an attribute, returning its value if it was set
+    pub fn remove(&mut self, name: &str) -> Option<Option<String>> {
+        let position = self.list.iter().position(|(key, _)| key == name)?;
+        Some(self.list.remove(position).1)
+    }
+
+    /// Iterates over the attributes and their values, in order
+    pub fn iter(&self) -> Iter<'_, (String, Option<String>)> {
+        self.list.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Attributes {
+    type Item = &'a (String, Option<String>);
+    type IntoIter = Iter<'a, (String, Option<String>)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Collects attributes in order, keeping the first occurrence of duplicates
+impl FromIterator<(String, Option<String>)> for Attributes {
+    fn from_iter<T: IntoIterator<Item = (String, Option<String>)>>(iter: T) -> Attributes {
+        let mut attributes = Attributes::new();
+
+        for (name, value) in iter {
+            attributes.add(name, value);
+        }
+
+        attributes
+    }
+}
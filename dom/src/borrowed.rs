@@ -0,0 +1,197 @@
+use crate::arena::{Arena, ElementData, NodeData, NodeId};
+use crate::doctype::Doctype;
+use crate::html_elements::{HTMLEnum, HTMLNode, HTMLNodeRef, SourcePosition, __SELF_CLOSED};
+use crate::serialize::{escape_text, write_element, Child, __RAW_TEXT};
+use crate::tree::TreeCell;
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter};
+
+/// Either text, a comment, a doctype or a [BorrowedNode], borrowing from the source they were parsed from.
+/// Read-only counterpart of [HTMLEnum]
+#[derive(Debug, Clone)]
+pub enum BorrowedEnum<'a> {
+    /// Text, only owned when character references had to be decoded
+    Text(Cow<'a, str>),
+    Node(BorrowedNode<'a>),
+    Comment(&'a str),
+    Doctype(Doctype),
+}
+
+/// A HTML tag whose name, attributes and text are slices of the source it was parsed from.
+/// Read-only counterpart of [HTMLNode]: it has no parent link and no id index
+#[derive(Debug, Clone, Default)]
+pub struct BorrowedNode<'a> {
+    /// The name of this tag, only owned when it had to be lowercased
+    pub name: Cow<'a, str>,
+
+    /// This tag's attributes, in the order they were met.
+    /// Names are only owned when they had to be lowercased, and values when character references had to be decoded
+    pub attributes: Vec<(Cow<'a, str>, Option<Cow<'a, str>>)>,
+
+    /// This tag's contents, whether they be tags or text
+    pub children: Vec<BorrowedEnum<'a>>,
+
+    /// Where this tag was parsed from, if it was
+    pub source_position: Option<SourcePosition>,
+}
+
+impl<'a> BorrowedNode<'a> {
+    /// Creates a tag without attributes nor children
+    pub fn new(name: Cow<'a, str>) -> BorrowedNode<'a> {
+        BorrowedNode {
+            name,
+            ..Default::default()
+        }
+    }
+
+    /// Adds an attribute, unless it is already set, like [Attributes::add](crate::attributes::Attributes::add).
+    /// Returns whether it was added
+    pub fn add_attribute(&mut self, attribute: Cow<'a, str>, value: Option<Cow<'a, str>>) -> bool {
+        if self.get_attribute(&attribute).is_some() {
+            return false;
+        }
+
+        self.attributes.push((attribute, value));
+        true
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Same as [HTMLNode::get_attribute], without copying the value
+    pub fn get_attribute(&self, name: &str) -> Option<Option<&str>> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.as_deref())
+    }
+
+    pub fn children(&self) -> &Vec<BorrowedEnum<'a>> {
+        &self.children
+    }
+
+    pub fn self_closing(&self) -> bool {
+        __SELF_CLOSED.contains(&self.name())
+    }
+
+    /// Whether this element contains raw text, which is never escaped (`<script>`, `<style>`)
+    pub fn raw_text(&self) -> bool {
+        __RAW_TEXT.contains(&self.name())
+    }
+
+    /// The concatenated text of every descendant text node, in document order
+    pub fn text_content(&self) -> String {
+        let mut buf = String::new();
+
+        for child in &self.children {
+            match child {
+                BorrowedEnum::Text(text) => buf += text,
+                BorrowedEnum::Node(node) => buf += &node.text_content(),
+                _ => {}
+            }
+        }
+
+        buf
+    }
+
+    /// Copies this tag and everything under it into an orphan [HTMLNode] tree
+    pub fn to_html_node(&self) -> HTMLNodeRef {
+        let mut arena = Arena::new();
+        let id = self.copy_into(&mut arena);
+
+        HTMLNode::at(&TreeCell::new(arena), id)
+    }
+
+    /// Copies this tag and everything under it into an arena, as a detached node
+    fn copy_into(&self, arena: &mut Arena) -> NodeId {
+        let id = arena.new_node(NodeData::Element(ElementData {
+            name: self.name.to_string(),
+            attributes: self
+                .attributes
+                .iter()
+                .map(|(attribute, value)| {
+                    (attribute.to_string(), value.as_deref().map(String::from))
+                })
+                .collect(),
+            source_position: self.source_position,
+        }));
+
+        for child in &self.children {
+            let child = match child {
+                BorrowedEnum::Text(text) => arena.new_text(text),
+                BorrowedEnum::Node(node) => node.copy_into(arena),
+                BorrowedEnum::Comment(comment) => {
+                    arena.new_node(NodeData::Comment(comment.to_string()))
+                }
+                BorrowedEnum::Doctype(doctype) => {
+                    arena.new_node(NodeData::Doctype(doctype.clone()))
+                }
+            };
+
+            arena.append_child(id, child);
+        }
+
+        id
+    }
+}
+
+impl BorrowedEnum<'_> {
+    /// Copies this node and everything under it into an owned [HTMLEnum]
+    pub fn to_html_enum(&self) -> HTMLEnum {
+        match self {
+            BorrowedEnum::Text(text) => HTMLEnum::Text(text.to_string()),
+            BorrowedEnum::Node(node) => HTMLEnum::Node(node.to_html_node()),
+            BorrowedEnum::Comment(comment) => HTMLEnum::Comment(comment.to_string()),
+            BorrowedEnum::Doctype(doctype) => HTMLEnum::Doctype(doctype.clone()),
+        }
+    }
+
+    /// Copies nodes and everything under them into owned [HTMLEnum]s, whose elements all share the same arena
+    pub fn to_html_enums(nodes: &[BorrowedEnum]) -> Vec<HTMLEnum> {
+        let mut arena = Arena::new();
+        let roots: Vec<Option<NodeId>> = nodes
+            .iter()
+            .map(|x| match x {
+                BorrowedEnum::Node(node) => Some(node.copy_into(&mut arena)),
+                _ => None,
+            })
+            .collect();
+        let cell = TreeCell::new(arena);
+
+        nodes
+            .iter()
+            .zip(roots)
+            .map(|(node, root)| match root {
+                Some(root) => HTMLEnum::Node(HTMLNode::at(&cell, root)),
+                None => node.to_html_enum(),
+            })
+            .collect()
+    }
+}
+
+impl Display for BorrowedEnum<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BorrowedEnum::Text(str) => write!(f, "{}", escape_text(str)),
+            BorrowedEnum::Node(elem) => write!(f, "{}", elem),
+            BorrowedEnum::Comment(str) => write!(f, "<!--{}-->", str),
+            BorrowedEnum::Doctype(doctype) => write!(f, "{}", doctype),
+        }
+    }
+}
+
+impl Display for BorrowedNode<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let attributes = self
+            .attributes
+            .iter()
+            .map(|(attribute, value)| (&**attribute, value.as_deref()));
+        let children = self.children.iter().map(|child| match child {
+            BorrowedEnum::Text(text) => Child::Text(text),
+            other => Child::Other(other),
+        });
+
+        write_element(f, &self.name, attributes, children)
+    }
+}
@@ -0,0 +1,168 @@
+use std::fmt::{Display, Formatter};
+
+/// Public identifier prefixes that put the document in quirks mode
+const QUIRKS_PUBLIC_PREFIXES: &[&str] = &[
+    "+//silmaril//dtd html pro v0r11 19970101//",
+    "-//as//dtd html 3.0 aswedit + extensions//",
+    "-//advasoft ltd//dtd html 3.0 aswedit + extensions//",
+    "-//ietf//dtd html 2.0 level 1//",
+    "-//ietf//dtd html 2.0 level 2//",
+    "-//ietf//dtd html 2.0 strict level 1//",
+    "-//ietf//dtd html 2.0 strict level 2//",
+    "-//ietf//dtd html 2.0 strict//",
+    "-//ietf//dtd html 2.0//",
+    "-//ietf//dtd html 2.1e//",
+    "-//ietf//dtd html 3.0//",
+    "-//ietf//dtd html 3.2 final//",
+    "-//ietf//dtd html 3.2//",
+    "-//ietf//dtd html 3//",
+    "-//ietf//dtd html level 0//",
+    "-//ietf//dtd html level 1//",
+    "-//ietf//dtd html level 2//",
+    "-//ietf//dtd html level 3//",
+    "-//ietf//dtd html strict level 0//",
+    "-//ietf//dtd html strict level 1//",
+    "-//ietf//dtd html strict level 2//",
+    "-//ietf//dtd html strict level 3//",
+    "-//ietf//dtd html strict//",
+    "-//ietf//dtd html//",
+    "-//metrius//dtd metrius presentational//",
+    "-//microsoft//dtd internet explorer 2.0 html strict//",
+    "-//microsoft//dtd internet explorer 2.0 html//",
+    "-//microsoft//dtd internet explorer 2.0 tables//",
+    "-//microsoft//dtd internet explorer 3.0 html strict//",
+    "-//microsoft//dtd internet explorer 3.0 html//",
+    "-//microsoft//dtd internet explorer 3.0 tables//",
+    "-//netscape comm. corp.//dtd html//",
+    "-//netscape comm. corp.//dtd strict html//",
+    "-//o'reilly and associates//dtd html 2.0//",
+    "-//o'reilly and associates//dtd html extended 1.0//",
+    "-//o'reilly and associates//dtd html extended relaxed 1.0//",
+    "-//sq//dtd html 2.0 hotmetal + extensions//",
+    "-//softquad software//dtd hotmetal pro 6.0::19990601::extensions to html 4.0//",
+    "-//softquad//dtd hotmetal pro 4.0::19971010::extensions to html 4.0//",
+    "-//spyglass//dtd html 2.0 extended//",
+    "-//sun microsystems corp.//dtd hotjava html//",
+    "-//sun microsystems corp.//dtd hotjava strict html//",
+    "-//w3c//dtd html 3 1995-03-24//",
+    "-//w3c//dtd html 3.2 draft//",
+    "-//w3c//dtd html 3.2 final//",
+    "-//w3c//dtd html 3.2//",
+    "-//w3c//dtd html 3.2s draft//",
+    "-//w3c//dtd html 4.0 frameset//",
+    "-//w3c//dtd html 4.0 transitional//",
+    "-//w3c//dtd html experimental 19960712//",
+    "-//w3c//dtd html experimental 970421//",
+    "-//w3c//dtd w3 html//",
+    "-//w3o//dtd w3 html 3.0//",
+    "-//webtechs//dtd mozilla html 2.0//",
+    "-//webtechs//dtd mozilla html//",
+];
+
+/// Public identifiers that put the document in quirks mode when they match exactly
+const QUIRKS_PUBLIC_IDS: &[&str] = &[
+    "-//w3o//dtd w3 html strict 3.0//en//",
+    "-/w3c/dtd html 4.0 transitional/en",
+    "html",
+];
+
+/// HTML 4.01 public identifiers: quirks mode without a system identifier, limited quirks with one
+const HTML4_PUBLIC_PREFIXES: &[&str] = &[
+    "-//w3c//dtd html 4.01 frameset//",
+    "-//w3c//dtd html 4.01 transitional//",
+];
+
+/// Public identifiers that put the document in limited quirks mode
+const LIMITED_QUIRKS_PUBLIC_PREFIXES: &[&str] = &[
+    "-//w3c//dtd xhtml 1.0 frameset//",
+    "-//w3c//dtd xhtml 1.0 transitional//",
+];
+
+/// The rendering mode a document's doctype selects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuirksMode {
+    /// Standards mode, selected by `<!DOCTYPE html>`
+    NoQuirks,
+    /// Almost standards mode, selected by transitional XHTML and HTML 4.01 doctypes
+    LimitedQuirks,
+    /// Legacy rendering, for documents with no doctype or an obsolete one
+    Quirks,
+}
+
+/// A document type declaration.
+/// Ex: for ```<!DOCTYPE html PUBLIC "-//W3C//DTD HTML 4.01//EN" "http://www.w3.org/TR/html4/strict.dtd">```,
+/// `name` = "html", with both identifiers set
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Doctype {
+    /// The name of the root element, lowercased
+    pub name: String,
+
+    /// The public identifier, if any
+    pub public_id: Option<String>,
+
+    /// The system identifier, if any
+    pub system_id: Option<String>,
+
+    /// Set when the doctype was malformed, which always selects quirks mode
+    pub force_quirks: bool,
+}
+
+impl Doctype {
+    /// Creates the standard `<!DOCTYPE html>` doctype
+    pub fn html5() -> Doctype {
+        Doctype {
+            name: String::from("html"),
+            ..Doctype::default()
+        }
+    }
+
+    /// Computes the rendering mode this doctype selects, following the HTML Living Standard
+    pub fn quirks_mode(&self) -> QuirksMode {
+        let public_id = self.public_id.as_deref().unwrap_or("").to_ascii_lowercase();
+        let system_id = self.system_id.as_deref().map(str::to_ascii_lowercase);
+        let starts_with = |prefixes: &[&str]| prefixes.iter().any(|x| public_id.starts_with(x));
+
+        if self.force_quirks
+            || self.name != "html"
+            || QUIRKS_PUBLIC_IDS.contains(&public_id.as_str())
+            || starts_with(QUIRKS_PUBLIC_PREFIXES)
+            || system_id.as_deref()
+                == Some("http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd")
+            || (system_id.is_none() && starts_with(HTML4_PUBLIC_PREFIXES))
+        {
+            QuirksMode::Quirks
+        } else if starts_with(LIMITED_QUIRKS_PUBLIC_PREFIXES)
+            || (system_id.is_some() && starts_with(HTML4_PUBLIC_PREFIXES))
+        {
+            QuirksMode::LimitedQuirks
+        } else {
+            QuirksMode::NoQuirks
+        }
+    }
+}
+
+/// Quotes a doctype identifier, with single quotes if it contains double ones
+fn quoted(id: &str) -> String {
+    if id.contains('"') {
+        format!("'{}'", id)
+    } else {
+        format!("\"{}\"", id)
+    }
+}
+
+impl Display for Doctype {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<!DOCTYPE {}", self.name)?;
+
+        match (&self.public_id, &self.system_id) {
+            (Some(public_id), Some(system_id)) => {
+                write!(f, " PUBLIC {} {}", quoted(public_id), quoted(system_id))?
+            }
+            (Some(public_id), None) => write!(f, " PUBLIC {}", quoted(public_id))?,
+            (None, Some(system_id)) => write!(f, " SYSTEM {}", quoted(system_id))?,
+            (None, None) => {}
+        }
+
+        write!(f, ">")
+    }
+}
@@ -1,96 +1,455 @@
-use super::html_elements::{HTMLEnum, HTMLNode, HTMLNodeRef, PrettyPrintable};
+use super::doctype::{Doctype, QuirksMode};
+use super::frozen::FrozenDocument;
+use super::hqueries::{HQuery, HQueryErr, HQueryable, HSelector};
+use super::html_elements::{Descendants, HTMLEnum, HTMLNode, HTMLNodeRef, PrettyPrintable};
+use crate::arena::NodeId;
+use crate::tree::Tree;
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 
 /// A HTML Document.
+///
+/// Its elements are indexed by id in the tree they live in, and kept indexed as they are edited or moved,
+/// until the document is dropped
 pub struct HTMLDocument {
-    doctype: Option<HTMLNodeRef>,
+    doctype: Option<Doctype>,
+
+    /// Comments met before the `<html>` element
+    prologue: Vec<HTMLEnum>,
+
+    html: HTMLNodeRef,
     head: HTMLNodeRef,
     body: HTMLNodeRef,
+
+    /// Comments met after the `<html>` element
+    epilogue: Vec<HTMLEnum>,
+}
+
+/// Controls what [HTMLDocument::from_tokens_with_options] keeps from the parsed tokens
+#[derive(Debug, Clone, Default)]
+pub struct DocumentOptions {
+    /// Keep comments where they appear in the document, instead of discarding them
+    pub keep_comments: bool,
+}
+
+/// Where the document is being built, deciding where whitespace and comments go.
+/// Follows the insertion modes of the HTML5 tree builder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Phase {
+    BeforeHtml,
+    BeforeHead,
+    InHead,
+    AfterHead,
+    InBody,
+    AfterBody,
+    AfterHtml,
 }
 
 impl HTMLDocument {
-    /// Parses a stream of standard HTML Tokens, and creates a fixed document from it,
-    /// correcting head elements not being in the head, and body elements not being in the body
-    fn recursive_sort(elements: Vec<HTMLEnum>, head: HTMLNodeRef, body: HTMLNodeRef) {
+    /// Parses a stream of standard HTML Tokens into this document,
+    /// correcting head elements not being in the head, and body elements not being in the body.
+    /// `parent` is the element body elements are added to
+    fn recursive_sort(
+        &mut self,
+        elements: Vec<HTMLEnum>,
+        parent: HTMLNodeRef,
+        phase: &mut Phase,
+        options: &DocumentOptions,
+    ) {
         let head_nodes: &[&str] = &[
             //These nodes are only valid in the head
             "title", "base", "link", "meta", "style", "script", "noscript", "template", "object",
         ];
 
-        let ignored = &["head", "body"];
-
         for elem in elements {
             //Recursively add stuff
 
             match elem {
-                HTMLEnum::Comment(_) => { /* IGNORE COMMENTS */ }
-                HTMLEnum::Text(text) => {
-                    body.borrow_mut().add_text(text);
+                HTMLEnum::Comment(_) if !options.keep_comments => {}
+                HTMLEnum::Doctype(_) => { /* Only the first doctype of the document is kept */ }
+                HTMLEnum::Text(text) if !text.chars().all(|c| c.is_ascii_whitespace()) => {
+                    *phase = (*phase).max(Phase::InBody);
+                    parent.add_text(text);
                 }
-                HTMLEnum::Node(html_node) => {
-                    //Figure out if its contents go to head or body
-                    //Do the same to children
+                HTMLEnum::Text(_) | HTMLEnum::Comment(_) => {
+                    //Whitespace and comments stay where they were met, as far as the tree allows
+                    let is_comment = matches!(elem, HTMLEnum::Comment(_));
 
-                    let children = std::mem::take(&mut html_node.borrow_mut().children);
+                    match *phase {
+                        Phase::BeforeHtml if is_comment => self.prologue.push(elem),
+                        Phase::BeforeHead if is_comment => {
+                            self.head.insert_before(vec![elem]);
+                        }
+                        Phase::BeforeHtml | Phase::BeforeHead => { /* Leading whitespace is dropped */
+                        }
+                        Phase::InHead => {
+                            self.head.add_children(vec![elem]);
+                        }
+                        Phase::AfterHead => {
+                            self.body.insert_before(vec![elem]);
+                        }
+                        Phase::AfterBody if is_comment => {
+                            self.html.add_children(vec![elem]);
+                        }
+                        Phase::AfterHtml if is_comment => self.epilogue.push(elem),
+                        Phase::InBody | Phase::AfterBody | Phase::AfterHtml => {
+                            parent.add_children(vec![elem]);
+                        }
+                    }
+                }
+                HTMLEnum::Node(html_node) => {
+                    //Figure out if it goes to head or body
+                    //Head elements keep their contents (title text, scripts...)
 
-                    let to_head: bool = head_nodes.contains(&html_node.borrow().name());
-                    let ignore: bool = ignored.contains(&html_node.borrow().name());
+                    let name = html_node.name();
 
-                    if to_head {
-                        Self::recursive_sort(children, head.clone(), body.clone());
-                        if !ignore {
-                            head.borrow_mut().add_child(html_node);
+                    if head_nodes.contains(&name.as_str()) {
+                        if *phase < Phase::InHead {
+                            *phase = Phase::InHead;
                         }
-                    } else {
-                        if !ignore {
-                            Self::recursive_sort(children, head.clone(), html_node.clone());
-                            body.borrow_mut().add_child(html_node);
-                        } else {
-                            Self::recursive_sort(children, head.clone(), body.clone());
+
+                        self.head.add_child(html_node);
+                        continue;
+                    }
+
+                    //Do the same to children
+                    let children = html_node.take_children();
+
+                    //The document's own html, head and body are already there,
+                    //only their attributes are kept
+                    let (target, opened, closed) = match name.as_str() {
+                        "html" => (self.html.clone(), Phase::BeforeHead, Phase::AfterHtml),
+                        "head" => (self.head.clone(), Phase::InHead, Phase::AfterHead),
+                        "body" => (self.body.clone(), Phase::InBody, Phase::AfterBody),
+                        _ => {
+                            *phase = (*phase).max(Phase::InBody);
+                            self.recursive_sort(children, html_node.clone(), phase, options);
+                            parent.add_child(html_node);
+                            continue;
                         }
+                    };
+
+                    Self::merge_attributes(&html_node, &target);
+
+                    //Only the first of these tags moves the document along
+                    let first = *phase < opened;
+                    if first {
+                        *phase = opened;
+                    }
+
+                    self.recursive_sort(children, parent.clone(), phase, options);
+
+                    if first {
+                        *phase = (*phase).max(closed);
                     }
                 }
             }
         }
     }
 
-    pub fn from_tokens(mut tokens: Vec<HTMLEnum>) -> HTMLDocument {
+    /// Copies the attributes of a duplicate `<html>`, `<head>` or `<body>` tag onto the document's own.
+    /// Like in browsers, attributes that are already set are left as they are
+    fn merge_attributes(from: &HTMLNode, to: &HTMLNode) {
+        let present = to.attributes();
+
+        for (attribute, value) in &from.attributes() {
+            if !present.contains(attribute) {
+                to.attribute(attribute.clone(), value.clone());
+            }
+        }
+    }
+
+    pub fn from_tokens(tokens: Vec<HTMLEnum>) -> HTMLDocument {
+        Self::from_tokens_with_options(tokens, &DocumentOptions::default())
+    }
+
+    /// Same as [from_tokens](HTMLDocument::from_tokens), with control over what is kept from the tokens
+    pub fn from_tokens_with_options(
+        mut tokens: Vec<HTMLEnum>,
+        options: &DocumentOptions,
+    ) -> HTMLDocument {
+        let html = HTMLNode::new();
         let head = HTMLNode::new();
         let body = HTMLNode::new();
 
-        let doctype: Option<HTMLNodeRef>;
+        let doctype: Option<Doctype> = tokens
+            .iter()
+            .position(|x| matches!(x, HTMLEnum::Doctype(_)))
+            .and_then(|index| match tokens.remove(index) {
+                HTMLEnum::Doctype(doctype) => Some(doctype),
+                _ => None,
+            });
 
-        match tokens.first() {
-            Some(HTMLEnum::Node(html)) if html.borrow().name.to_lowercase() == "!doctype" => {
-                doctype = Some(html.clone());
-                tokens.remove(0);
-            }
-            _ => {
-                doctype = None;
-            }
-        }
+        html.set_name("html");
+        head.set_name("head");
+        body.set_name("body");
 
-        Self::recursive_sort(tokens, head.clone(), body.clone());
+        html.add_child(head.clone());
+        html.add_child(body.clone());
 
-        head.borrow_mut().name = "head".to_string();
-        body.borrow_mut().name = "body".to_string();
+        let mut document = Self::from_parts(doctype, vec![], html, head, body, vec![]);
+
+        let body = document.body.clone();
+        document.recursive_sort(tokens, body, &mut Phase::BeforeHtml, options);
+        document
+    }
+
+    /// Builds a document out of an already sorted tree, indexing the ids of the elements under `html`.
+    /// `head` and `body` must be children of `html`
+    pub(crate) fn from_parts(
+        doctype: Option<Doctype>,
+        prologue: Vec<HTMLEnum>,
+        html: HTMLNodeRef,
+        head: HTMLNodeRef,
+        body: HTMLNodeRef,
+        epilogue: Vec<HTMLEnum>,
+    ) -> HTMLDocument {
+        // Every element under the root, and every element added under it later, joins its id index
+        html.write(|tree, id| tree.add_document(id));
 
         HTMLDocument {
             doctype,
+            prologue,
+            html,
             head,
             body,
+            epilogue,
+        }
+    }
+
+    /// The root `<html>` element of the document
+    pub fn document_element(&self) -> HTMLNodeRef {
+        self.html.clone()
+    }
+
+    /// The `<head>` element of the document
+    pub fn head(&self) -> HTMLNodeRef {
+        self.head.clone()
+    }
+
+    /// The `<body>` element of the document
+    pub fn body(&self) -> HTMLNodeRef {
+        self.body.clone()
+    }
+
+    /// The first `<title>` element of the document, if any
+    fn title_element(&self) -> Option<HTMLNodeRef> {
+        self.query_selector(&HQuery::new().name("title"))
+    }
+
+    /// The document's title, with its whitespace stripped and collapsed.
+    /// Empty if the document has no `<title>`
+    pub fn title(&self) -> String {
+        match self.title_element() {
+            None => String::new(),
+            Some(title) => title
+                .text_content()
+                .split_ascii_whitespace()
+                .collect::<Vec<&str>>()
+                .join(" "),
+        }
+    }
+
+    /// Replaces the document's title, creating a `<title>` element in the head if there is none
+    pub fn set_title(&mut self, title: &str) {
+        let element = match self.title_element() {
+            Some(element) => element,
+            None => {
+                let element = HTMLNode::new();
+                element.set_name("title");
+                self.head.add_child(element.clone());
+                element
+            }
+        };
+
+        // Detaches the elements being replaced, taking them out of the id index
+        element.set_text_content(title);
+    }
+
+    /// Returns the first element of the document matching the selector, in document order.
+    /// Same as [HQueryable::query_selector], without having to import the trait
+    pub fn query_selector<S>(&self, selector: &S) -> Option<HTMLNodeRef>
+    where
+        S: HSelector + ?Sized,
+    {
+        HQueryable::query_selector(self, selector)
+    }
+
+    /// Returns every element of the document matching the selector, in document order.
+    /// Same as [HQueryable::query_selector_all], without having to import the trait
+    pub fn query_selector_all<S>(&self, selector: &S) -> Vec<HTMLNodeRef>
+    where
+        S: HSelector + ?Sized,
+    {
+        HQueryable::query_selector_all(self, selector)
+    }
+
+    /// Maps the id of every element that has a non-empty one to that element.
+    ///
+    /// ### Errors
+    /// Returns [DuplicateId](HQueryErr::DuplicateId) for the first id shared by several elements
+    pub fn id_index(&self) -> Result<HashMap<String, HTMLNodeRef>, HQueryErr> {
+        if let Some(error) = self.validate().into_iter().next() {
+            return Err(error);
         }
+
+        self.html.read(|tree, root| {
+            let index = tree
+                .document_index(root)
+                .expect("A document is indexed until dropped");
+
+            Ok(index
+                .iter()
+                .filter_map(|(id, nodes)| Some((id.clone(), tree.node(*nodes.first()?))))
+                .collect())
+        })
+    }
+
+    /// Checks that the document is valid, returning every problem found, in document order.
+    /// Currently, only ids shared by several elements are reported, along with where each of them was parsed
+    pub fn validate(&self) -> Vec<HQueryErr> {
+        self.html.read(|tree, root| {
+            let arena = &tree.arena;
+            let mut errors = vec![];
+
+            for node in std::iter::once(root).chain(arena.descendants(root)) {
+                let id = match arena
+                    .element(node)
+                    .and_then(|x| x.attribute("id"))
+                    .filter(|x| !x.is_empty())
+                {
+                    Some(id) => id,
+                    None => continue,
+                };
+
+                let duplicates = Self::elements_by_id(tree, root, id);
+
+                // Only reported once, on its first element
+                if duplicates.len() > 1 && duplicates[0] == node {
+                    errors.push(HQueryErr::DuplicateId {
+                        id: id.to_string(),
+                        positions: duplicates
+                            .iter()
+                            .filter_map(|x| arena.element(*x)?.source_position)
+                            .collect(),
+                    });
+                }
+            }
+
+            errors
+        })
+    }
+
+    /// Every element of the document rooted at `root` with the given id, in document order
+    fn elements_by_id(tree: &Tree, root: NodeId, id: &str) -> Vec<NodeId> {
+        let candidates = match tree.document_index(root).and_then(|x| x.get(id)) {
+            None => return vec![],
+            Some(nodes) => nodes,
+        };
+
+        if candidates.len() < 2 {
+            return candidates.clone();
+        }
+
+        // The index is unordered: sort duplicates by walking the document
+        std::iter::once(root)
+            .chain(tree.arena.descendants(root))
+            .filter(|x| candidates.contains(x))
+            .collect()
+    }
+
+    /// The first element of the document with the given id, in document order
+    pub fn get_element_by_id(&self, id: &str) -> Option<HTMLNodeRef> {
+        self.html.read(|tree, root| {
+            let first = Self::elements_by_id(tree, root, id).into_iter().next()?;
+            Some(tree.node(first))
+        })
+    }
+
+    /// Every element having all the given space-separated classes, in document order
+    pub fn get_elements_by_class_name(&self, class_names: &str) -> Vec<HTMLNodeRef> {
+        let classes: Vec<&str> = class_names.split_ascii_whitespace().collect();
+
+        // No class given matches nothing
+        if classes.is_empty() {
+            return vec![];
+        }
+
+        let query = classes
+            .into_iter()
+            .fold(HQuery::new(), |query, class| query.class(class));
+
+        self.query_selector_all(&query)
+    }
+
+    /// Every element with the given tag name, in document order. `*` matches every element
+    pub fn get_elements_by_tag_name(&self, name: &str) -> Vec<HTMLNodeRef> {
+        match name {
+            "*" => self.query_scope().collect(),
+            name => self.query_selector_all(&HQuery::new().name(name)),
+        }
+    }
+
+    /// Copies this document into a [FrozenDocument], which can be sent to and shared between threads
+    pub fn freeze(&self) -> FrozenDocument {
+        FrozenDocument::new(
+            self.doctype.clone(),
+            &self.prologue,
+            &HTMLEnum::Node(self.html.clone()),
+            &self.epilogue,
+        )
+    }
+
+    /// The document's doctype, if it has one
+    pub fn doctype(&self) -> Option<&Doctype> {
+        self.doctype.as_ref()
+    }
+
+    /// The rendering mode selected by the document's doctype.
+    /// Documents without a doctype are rendered in quirks mode
+    pub fn quirks_mode(&self) -> QuirksMode {
+        match &self.doctype {
+            None => QuirksMode::Quirks,
+            Some(doctype) => doctype.quirks_mode(),
+        }
+    }
+}
+
+/// Searches the whole document, `<html>` element included
+impl HQueryable for HTMLDocument {
+    fn query_scope(&self) -> Descendants {
+        Descendants::new(vec![self.html.clone()])
+    }
+
+    fn quirks_mode(&self) -> QuirksMode {
+        HTMLDocument::quirks_mode(self)
+    }
+}
+
+/// Takes the document's id index out of its tree, which other documents may share
+impl Drop for HTMLDocument {
+    fn drop(&mut self) {
+        self.html.write(|tree, root| tree.remove_document(root));
     }
 }
 
 impl Display for HTMLDocument {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         if let Some(d) = &self.doctype {
-            write!(f, "{}", d.borrow())?;
+            write!(f, "{}", d)?;
+        }
+
+        for comment in &self.prologue {
+            write!(f, "{}", comment)?;
         }
 
-        write!(f, "{}", self.head.borrow())?;
-        write!(f, "{}", self.body.borrow())
+        write!(f, "{}", self.html)?;
+
+        for comment in &self.epilogue {
+            write!(f, "{}", comment)?;
+        }
+
+        Ok(())
     }
 }
 
@@ -99,16 +458,19 @@ impl PrettyPrintable for HTMLDocument {
         let mut buf = String::new();
 
         if let Some(d) = &self.doctype {
-            buf += &format!(
-                "{}{}\n",
-                "\t".repeat(depth),
-                d.borrow().pretty_fmt_rec(depth)
-            );
+            buf += &format!("{}{}\n", "\t".repeat(depth), d);
         }
 
-        buf += &self.head.borrow().pretty_fmt_rec(depth);
-        buf += "\n";
-        buf += &self.body.borrow().pretty_fmt_rec(depth);
+        for comment in &self.prologue {
+            buf += &comment.pretty_fmt_rec(depth);
+        }
+
+        buf += &self.html.pretty_fmt_rec(depth);
+
+        for comment in &self.epilogue {
+            buf += "\n";
+            buf += &comment.pretty_fmt_rec(depth);
+        }
 
         buf
     }
@@ -0,0 +1,191 @@
+use crate::arena::{Arena, ArenaDescendants, NodeData, NodeId};
+use crate::doctype::{Doctype, QuirksMode};
+use crate::document::HTMLDocument;
+use crate::html_elements::{HTMLEnum, HTMLNode};
+use crate::tree::TreeCell;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+/// An immutable copy of a [HTMLDocument], stored in an [Arena] of its own.
+///
+/// Unlike [HTMLDocument], it is never locked: it can be read from many threads at once, as is.
+/// Clones share the same tree.
+/// Use [thaw](FrozenDocument::thaw) to get back an editable document
+#[derive(Debug, Clone)]
+pub struct FrozenDocument {
+    inner: Arc<FrozenInner>,
+}
+
+#[derive(Debug)]
+struct FrozenInner {
+    arena: Arena,
+    doctype: Option<Doctype>,
+    prologue: Vec<NodeId>,
+    html: NodeId,
+    head: NodeId,
+    body: NodeId,
+    epilogue: Vec<NodeId>,
+
+    /// The first element with each non-empty id, in document order
+    ids: HashMap<String, NodeId>,
+}
+
+impl FrozenDocument {
+    /// Copies the parts of a document into a new arena
+    pub(crate) fn new(
+        doctype: Option<Doctype>,
+        prologue: &[HTMLEnum],
+        html: &HTMLEnum,
+        epilogue: &[HTMLEnum],
+    ) -> FrozenDocument {
+        let mut arena = Arena::new();
+
+        let prologue = prologue.iter().map(|x| arena.import(x)).collect();
+        let html = arena.import(html);
+        let epilogue = epilogue.iter().map(|x| arena.import(x)).collect();
+
+        let child_named = |name: &str| {
+            arena
+                .children(html)
+                .find(|x| arena.get(*x).name() == Some(name))
+                .expect("A document always has a head and a body")
+        };
+        let (head, body) = (child_named("head"), child_named("body"));
+
+        let mut ids = HashMap::new();
+
+        for node in std::iter::once(html).chain(arena.descendants(html)) {
+            if let Some(id) = arena
+                .element(node)
+                .and_then(|x| x.attribute("id"))
+                .filter(|x| !x.is_empty())
+            {
+                ids.entry(id.to_string()).or_insert(node);
+            }
+        }
+
+        FrozenDocument {
+            inner: Arc::new(FrozenInner {
+                arena,
+                doctype,
+                prologue,
+                html,
+                head,
+                body,
+                epilogue,
+                ids,
+            }),
+        }
+    }
+
+    /// The arena holding the document's nodes
+    pub fn arena(&self) -> &Arena {
+        &self.inner.arena
+    }
+
+    /// The root `<html>` element of the document
+    pub fn document_element(&self) -> NodeId {
+        self.inner.html
+    }
+
+    /// The `<head>` element of the document
+    pub fn head(&self) -> NodeId {
+        self.inner.head
+    }
+
+    /// The `<body>` element of the document
+    pub fn body(&self) -> NodeId {
+        self.inner.body
+    }
+
+    /// The document's doctype, if it has one
+    pub fn doctype(&self) -> Option<&Doctype> {
+        self.inner.doctype.as_ref()
+    }
+
+    /// The rendering mode selected by the document's doctype, see [HTMLDocument::quirks_mode]
+    pub fn quirks_mode(&self) -> QuirksMode {
+        match &self.inner.doctype {
+            None => QuirksMode::Quirks,
+            Some(doctype) => doctype.quirks_mode(),
+        }
+    }
+
+    /// Iterates over every node under the `<html>` element, in document order
+    pub fn descendants(&self) -> ArenaDescendants<'_> {
+        self.inner.arena.descendants(self.inner.html)
+    }
+
+    /// The first element with the given id, in document order
+    pub fn get_element_by_id(&self, id: &str) -> Option<NodeId> {
+        self.inner.ids.get(id).copied()
+    }
+
+    /// The document's title, see [HTMLDocument::title]
+    pub fn title(&self) -> String {
+        let arena = &self.inner.arena;
+
+        match self
+            .descendants()
+            .find(|x| arena.get(*x).name() == Some("title"))
+        {
+            None => String::new(),
+            Some(title) => arena
+                .text_content(title)
+                .split_ascii_whitespace()
+                .collect::<Vec<&str>>()
+                .join(" "),
+        }
+    }
+
+    /// Copies this document back into an editable [HTMLDocument], as it was when it was frozen.
+    /// Nodes keep their place in the copied arena, and ids are indexed again
+    pub fn thaw(&self) -> HTMLDocument {
+        let inner = &self.inner;
+        let cell = TreeCell::new(inner.arena.clone());
+
+        let node = |id: NodeId| match &inner.arena[id] {
+            NodeData::Element(_) => HTMLEnum::Node(HTMLNode::at(&cell, id)),
+            NodeData::Text(text) => HTMLEnum::Text(text.clone()),
+            NodeData::Comment(comment) => HTMLEnum::Comment(comment.clone()),
+            NodeData::Doctype(doctype) => HTMLEnum::Doctype(doctype.clone()),
+        };
+
+        HTMLDocument::from_parts(
+            inner.doctype.clone(),
+            inner.prologue.iter().map(|x| node(*x)).collect(),
+            HTMLNode::at(&cell, inner.html),
+            HTMLNode::at(&cell, inner.head),
+            HTMLNode::at(&cell, inner.body),
+            inner.epilogue.iter().map(|x| node(*x)).collect(),
+        )
+    }
+}
+
+impl From<&HTMLDocument> for FrozenDocument {
+    fn from(document: &HTMLDocument) -> FrozenDocument {
+        document.freeze()
+    }
+}
+
+impl Display for FrozenDocument {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let inner = &self.inner;
+
+        if let Some(d) = &inner.doctype {
+            write!(f, "{}", d)?;
+        }
+
+        for id in inner
+            .prologue
+            .iter()
+            .chain(std::iter::once(&inner.html))
+            .chain(inner.epilogue.iter())
+        {
+            write!(f, "{}", inner.arena.html(*id))?;
+        }
+
+        Ok(())
+    }
+}
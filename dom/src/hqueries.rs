@@ -1,8 +1,12 @@
-use crate::html_elements::{HTMLNode, HTMLNodeRef};
-use std::collections::HashSet;
+use crate::arena::{Arena, ElementData, NodeData, NodeId};
+use crate::doctype::QuirksMode;
+use crate::html_elements::{Descendants, HTMLNode, HTMLNodeRef, SourcePosition};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::ops::Add;
 
 /// A representation of standard dom queries, but only on one element
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct HQuery {
     ///Tag name of the object
     name: Option<String>,
@@ -13,103 +17,921 @@ pub struct HQuery {
     /// IDentifier of the object
     id: Option<String>,
 
-    /// Attribute-value pairs
-    attributes: Vec<(String, Option<String>)>,
+    /// Conditions on attributes
+    attributes: Vec<HAttributeQuery>,
+
+    /// Conditions on the element's position in the tree
+    pseudo_classes: Vec<HPseudoClass>,
 }
 
-/// A simplified DOM query, implementing classes, tag names, identifiers, and attribute-value pairs
-impl HQuery {
-    pub fn matches(&self, html_node: &HTMLNode) -> bool {
-        // Match name
-        if let Some(expected_name) = &self.name {
-            if html_node.name() != expected_name {
-                return false;
-            }
+/// The specificity of a selector, deciding which selector wins when several match the same element.
+/// Compares as the CSS (a, b, c) tuple: ids first, then classes, then types
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HSpecificity {
+    /// Number of id selectors
+    pub ids: u32,
+    /// Number of class, attribute and pseudo-class selectors
+    pub classes: u32,
+    /// Number of type selectors
+    pub types: u32,
+}
+
+impl Add for HSpecificity {
+    type Output = HSpecificity;
+
+    fn add(self, other: HSpecificity) -> HSpecificity {
+        HSpecificity {
+            ids: self.ids + other.ids,
+            classes: self.classes + other.classes,
+            types: self.types + other.types,
         }
+    }
+}
 
-        // Match id
-        if let Some(expected_id) = &self.id {
-            let id_attr = html_node
-                .attributes
+impl Display for HSpecificity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}, {}, {})", self.ids, self.classes, self.types)
+    }
+}
+
+/// The `An+B` argument of `:nth-*` pseudo-classes, matching every position `a*n + b` for n >= 0.
+/// Positions start at 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HNth {
+    pub a: i32,
+    pub b: i32,
+}
+
+impl HNth {
+    /// Whether an element at the given position (starting at 1) is selected
+    pub fn matches(&self, position: usize) -> bool {
+        let position = position as i64;
+        let (a, b) = (self.a as i64, self.b as i64);
+
+        if a == 0 {
+            return position == b;
+        }
+
+        let steps = position - b;
+        steps % a == 0 && steps / a >= 0
+    }
+}
+
+/// A pseudo-class, selecting elements by their position in the tree, or by other queries.
+/// For positions, elements without a parent are treated as their parent's only child
+#[derive(Debug)]
+pub enum HPseudoClass {
+    /// `:first-child`
+    FirstChild,
+    /// `:last-child`
+    LastChild,
+    /// `:only-child`
+    OnlyChild,
+    /// `:first-of-type`
+    FirstOfType,
+    /// `:last-of-type`
+    LastOfType,
+    /// `:only-of-type`
+    OnlyOfType,
+    /// `:nth-child(An+B [of S])`, counting only the siblings matching S if given
+    NthChild(HNth, Option<HQueryList>),
+    /// `:nth-last-child(An+B [of S])`, counting from the last child
+    NthLastChild(HNth, Option<HQueryList>),
+    /// `:nth-of-type(An+B)`
+    NthOfType(HNth),
+    /// `:nth-last-of-type(An+B)`
+    NthLastOfType(HNth),
+    /// `:empty`, elements without any child element or text
+    Empty,
+    /// `:root`, elements without a parent
+    Root,
+    /// `:not(S)`, elements matching none of the queries
+    Not(HQueryList),
+    /// `:is(S)`, elements matching any of the queries
+    Is(HQueryList),
+    /// `:where(S)`, same as `:is(S)`, but adds nothing to the specificity
+    Where(HQueryList),
+    /// `:has(S)`, elements with a relative matching any of the queries.
+    /// Each query starts with how its outermost element relates to the matched one, `> img` being `(Direct, img)`
+    Has(Vec<(HChildType, HCombinedQuery)>),
+}
+
+impl HPseudoClass {
+    /// The specificity of the pseudo-class: that of a class,
+    /// except for the logical ones, which take the specificity of their most specific argument
+    pub fn specificity(&self) -> HSpecificity {
+        let class = HSpecificity {
+            classes: 1,
+            ..HSpecificity::default()
+        };
+
+        match self {
+            HPseudoClass::NthChild(_, Some(of)) | HPseudoClass::NthLastChild(_, Some(of)) => {
+                class + of.specificity()
+            }
+            HPseudoClass::Not(list) | HPseudoClass::Is(list) => list.specificity(),
+            HPseudoClass::Where(_) => HSpecificity::default(),
+            HPseudoClass::Has(relatives) => relatives
                 .iter()
-                .find(|(attr, _)| attr.to_lowercase() == "id");
+                .map(|(_, query)| query.specificity())
+                .max()
+                .unwrap_or_default(),
+            _ => class,
+        }
+    }
 
-            // Expect an ID field to be set
-            if id_attr.is_none() {
-                return false;
+    fn matches(&self, element: Element, mode: QuirksMode) -> bool {
+        let same_type = |x: Element| x.name() == element.name();
+
+        match self {
+            HPseudoClass::FirstChild => element_position(element, false, |_| true) == 1,
+            HPseudoClass::LastChild => element_position(element, true, |_| true) == 1,
+            HPseudoClass::OnlyChild => {
+                HPseudoClass::FirstChild.matches(element, mode)
+                    && HPseudoClass::LastChild.matches(element, mode)
+            }
+            HPseudoClass::FirstOfType => element_position(element, false, same_type) == 1,
+            HPseudoClass::LastOfType => element_position(element, true, same_type) == 1,
+            HPseudoClass::OnlyOfType => {
+                HPseudoClass::FirstOfType.matches(element, mode)
+                    && HPseudoClass::LastOfType.matches(element, mode)
             }
+            HPseudoClass::NthChild(nth, of) | HPseudoClass::NthLastChild(nth, of) => {
+                let counted = |x: Element| {
+                    of.as_ref()
+                        .is_none_or(|query| query.matches_element(x, mode))
+                };
 
-            // Expect the ID to be as specified
-            let (_, val) = id_attr.unwrap();
-            let actual_id = val.clone().unwrap_or("".to_lowercase());
+                if of
+                    .as_ref()
+                    .is_some_and(|query| !query.matches_element(element, mode))
+                {
+                    return false;
+                }
 
-            if &actual_id != expected_id {
-                return false;
+                let from_end = matches!(self, HPseudoClass::NthLastChild(..));
+                nth.matches(element_position(element, from_end, counted))
+            }
+            HPseudoClass::NthOfType(nth) => {
+                nth.matches(element_position(element, false, same_type))
+            }
+            HPseudoClass::NthLastOfType(nth) => {
+                nth.matches(element_position(element, true, same_type))
+            }
+            HPseudoClass::Empty => {
+                element
+                    .arena
+                    .children(element.id)
+                    .all(|x| match &element.arena[x] {
+                        NodeData::Text(text) => text.is_empty(),
+                        NodeData::Element(_) => false,
+                        NodeData::Comment(_) | NodeData::Doctype(_) => true,
+                    })
             }
+            HPseudoClass::Root => element.parent().is_none(),
+            HPseudoClass::Not(list) => !list.matches_element(element, mode),
+            HPseudoClass::Is(list) | HPseudoClass::Where(list) => {
+                list.matches_element(element, mode)
+            }
+            HPseudoClass::Has(relatives) => relatives.iter().any(|(child_type, query)| {
+                // Only the elements after this one can be related to it
+                let candidates: Vec<Element> = match child_type {
+                    HChildType::Direct | HChildType::Indirect => element.descendants().collect(),
+                    HChildType::Adjacent | HChildType::Sibling => element
+                        .following_siblings()
+                        .flat_map(|sibling| std::iter::once(sibling).chain(sibling.descendants()))
+                        .collect(),
+                };
+
+                candidates
+                    .into_iter()
+                    .any(|candidate| query.matches_relative(candidate, element, *child_type, mode))
+            }),
         }
+    }
+}
 
-        // Match attributes
-        for (attribute, value) in &self.attributes {
-            let actual_value = html_node.attributes.get(attribute); //Opt<Opt<Str>>
+/// The position of an element among its siblings, starting at 1 from the first or the last one,
+/// only counting the siblings `counted` accepts
+fn element_position<F>(element: Element, from_end: bool, counted: F) -> usize
+where
+    F: Fn(Element) -> bool,
+{
+    let siblings = match from_end {
+        false => element.preceding_siblings().filter(|x| counted(*x)).count(),
+        true => element.following_siblings().filter(|x| counted(*x)).count(),
+    };
 
-            if actual_value.is_none() {
-                return false;
+    1 + siblings
+}
+
+/// How an attribute's value is compared in an attribute query
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HAttributeOperator {
+    /// The value is exactly the expected one (`[a=v]`)
+    Equals,
+    /// The value starts with the expected one (`[a^=v]`)
+    Prefix,
+    /// The value ends with the expected one (`[a$=v]`)
+    Suffix,
+    /// The value contains the expected one (`[a*=v]`)
+    Substring,
+    /// The value is a whitespace-separated list containing the expected one (`[a~=v]`)
+    Includes,
+    /// The value is the expected one, or starts with it followed by a hyphen (`[a|=v]`)
+    DashMatch,
+}
+
+/// A condition on one attribute of an element
+#[derive(Debug, Clone)]
+struct HAttributeQuery {
+    name: String,
+
+    /// None when the attribute only has to be defined
+    value: Option<(HAttributeOperator, String)>,
+
+    /// Whether values are compared ignoring ASCII case (the `i` flag)
+    case_insensitive: bool,
+}
+
+impl HAttributeQuery {
+    fn matches(&self, element: Element) -> bool {
+        // A valueless attribute has an empty value
+        let actual = match element.data().attributes.get(&self.name) {
+            None => return false,
+            Some(actual) => actual.unwrap_or(""),
+        };
+
+        let (operator, expected) = match &self.value {
+            None => return true,
+            Some((operator, expected)) => (operator, expected.as_str()),
+        };
+
+        let (actual, expected) = if self.case_insensitive {
+            (actual.to_ascii_lowercase(), expected.to_ascii_lowercase())
+        } else {
+            (actual.to_string(), expected.to_string())
+        };
+
+        match operator {
+            HAttributeOperator::Equals => actual == expected,
+            // An empty value never matches these
+            HAttributeOperator::Prefix => !expected.is_empty() && actual.starts_with(&expected),
+            HAttributeOperator::Suffix => !expected.is_empty() && actual.ends_with(&expected),
+            HAttributeOperator::Substring => !expected.is_empty() && actual.contains(&expected),
+            HAttributeOperator::Includes => {
+                !expected.is_empty()
+                    && !expected.contains(|c: char| c.is_ascii_whitespace())
+                    && actual.split_ascii_whitespace().any(|x| x == expected)
+            }
+            HAttributeOperator::DashMatch => {
+                actual == expected
+                    || actual
+                        .strip_prefix(&expected)
+                        .is_some_and(|rest| rest.starts_with('-'))
             }
+        }
+    }
+}
+
+/// A simplified DOM query, implementing classes, tag names, identifiers, and attribute-value pairs
+impl HQuery {
+    /// Creates a query matching every element
+    pub fn new() -> HQuery {
+        HQuery::default()
+    }
+
+    /// Restricts the query to elements with the given tag name
+    pub fn name(mut self, name: &str) -> HQuery {
+        self.name = Some(name.to_lowercase());
+        self
+    }
+
+    /// Restricts the query to elements having the given class
+    pub fn class(mut self, class: &str) -> HQuery {
+        self.classes.push(class.to_string());
+        self
+    }
+
+    /// Restricts the query to the element with the given identifier
+    pub fn id(mut self, id: &str) -> HQuery {
+        self.id = Some(id.to_string());
+        self
+    }
 
-            // A None value means we just want the attribute to be defined
-            if value.is_none() {
-                continue;
+    /// Restricts the query to elements defining the given attribute.
+    /// If a value is given, the attribute must be set to exactly that value
+    pub fn attribute(self, attribute: &str, value: Option<&str>) -> HQuery {
+        match value {
+            None => self.push_attribute(attribute, None, false),
+            Some(value) => {
+                self.attribute_matching(attribute, HAttributeOperator::Equals, value, false)
             }
+        }
+    }
+
+    /// Restricts the query to elements whose attribute value compares to `value` with the given operator.
+    /// `case_insensitive` ignores ASCII case in the comparison, like the `i` flag of CSS
+    pub fn attribute_matching(
+        self,
+        attribute: &str,
+        operator: HAttributeOperator,
+        value: &str,
+        case_insensitive: bool,
+    ) -> HQuery {
+        self.push_attribute(
+            attribute,
+            Some((operator, value.to_string())),
+            case_insensitive,
+        )
+    }
+
+    /// Restricts the query to elements selected by a pseudo-class
+    pub fn pseudo_class(mut self, pseudo_class: HPseudoClass) -> HQuery {
+        self.pseudo_classes.push(pseudo_class);
+        self
+    }
+
+    fn push_attribute(
+        mut self,
+        attribute: &str,
+        value: Option<(HAttributeOperator, String)>,
+        case_insensitive: bool,
+    ) -> HQuery {
+        self.attributes.push(HAttributeQuery {
+            name: attribute.to_lowercase(),
+            value,
+            case_insensitive,
+        });
+        self
+    }
 
-            let actual_value = actual_value.unwrap();
+    /// Whether an element matches this query, in a standards mode document
+    pub fn matches(&self, html_node: &HTMLNode) -> bool {
+        self.matches_in(html_node, QuirksMode::NoQuirks)
+    }
+
+    /// The specificity of this query, the universal selector counting for nothing
+    pub fn specificity(&self) -> HSpecificity {
+        let own = HSpecificity {
+            ids: self.id.iter().count() as u32,
+            classes: (self.classes.len() + self.attributes.len()) as u32,
+            types: self.name.iter().count() as u32,
+        };
+
+        self.pseudo_classes
+            .iter()
+            .fold(own, |total, x| total + x.specificity())
+    }
+
+    /// Whether an element matches this query, in a document rendered in the given mode.
+    /// In quirks mode, classes and identifiers are compared ignoring ASCII case
+    pub fn matches_in(&self, html_node: &HTMLNode, mode: QuirksMode) -> bool {
+        html_node.read(|tree, id| self.matches_element(Element::new(&tree.arena, id), mode))
+    }
+
+    /// Same as [matches_in](HQuery::matches_in), on an element of a locked tree
+    fn matches_element(&self, element: Element, mode: QuirksMode) -> bool {
+        let same = |expected: &str, actual: &str| match mode {
+            QuirksMode::Quirks => expected.eq_ignore_ascii_case(actual),
+            _ => expected == actual,
+        };
 
-            if actual_value.clone().unwrap_or("".to_string()) != value.clone().unwrap() {
+        // Match name
+        if let Some(expected_name) = &self.name {
+            if element.name() != expected_name {
                 return false;
             }
         }
 
-        // Match classes
-        let defined_classes = html_node.attributes.get("class");
-        if defined_classes.is_none() {
-            return self.classes.len() == 0;
+        // Match id. An empty id is the same as none
+        if let Some(expected_id) = &self.id {
+            match element.data().attribute("id") {
+                Some(id) if !id.is_empty() && same(expected_id, id) => {}
+                _ => return false,
+            }
         }
 
-        let mut classes_table: HashSet<String> = HashSet::new();
-        defined_classes
-            .unwrap()
-            .clone()
-            .unwrap_or("".to_string())
-            .split(" ")
-            .filter(|s| s.len() != 0)
-            .for_each(|x| {
-                classes_table.insert(x.to_string());
-            });
+        // Match classes, separated by any ASCII whitespace
+        if !self.classes.is_empty() {
+            let classes = element.data().attribute("class").unwrap_or("");
 
-        for class in &self.classes {
-            if classes_table.get(class).is_none() {
-                return false;
+            for expected in &self.classes {
+                if !classes
+                    .split(is_html_whitespace)
+                    .any(|class| same(expected, class))
+                {
+                    return false;
+                }
             }
         }
 
-        return true;
+        // Match attributes
+        if !self.attributes.iter().all(|x| x.matches(element)) {
+            return false;
+        }
+
+        // Match pseudo-classes
+        self.pseudo_classes.iter().all(|x| x.matches(element, mode))
     }
 }
 
-#[derive(Debug)]
+/// The ASCII whitespace of HTML, which separates classes: tab, line feed, form feed, carriage return and space
+fn is_html_whitespace(x: char) -> bool {
+    matches!(x, '\t' | '\n' | '\u{0C}' | '\r' | ' ')
+}
+
+/// An element of a locked tree, as queries look at it
+#[derive(Clone, Copy)]
+struct Element<'a> {
+    arena: &'a Arena,
+    id: NodeId,
+}
+
+impl<'a> Element<'a> {
+    fn new(arena: &'a Arena, id: NodeId) -> Element<'a> {
+        Element { arena, id }
+    }
+
+    fn data(self) -> &'a ElementData {
+        self.arena
+            .element(self.id)
+            .expect("Queries only look at elements")
+    }
+
+    fn name(self) -> &'a str {
+        &self.data().name
+    }
+
+    /// Keeps the elements among the given nodes
+    fn elements<I>(self, nodes: I) -> impl Iterator<Item = Element<'a>>
+    where
+        I: Iterator<Item = NodeId>,
+    {
+        let arena = self.arena;
+
+        nodes
+            .filter(move |x| arena.element(*x).is_some())
+            .map(move |x| Element::new(arena, x))
+    }
+
+    fn parent(self) -> Option<Element<'a>> {
+        let parent = self.arena.get(self.id).parent()?;
+        Some(Element::new(self.arena, parent))
+    }
+
+    fn ancestors(self) -> impl Iterator<Item = Element<'a>> {
+        self.elements(self.arena.ancestors(self.id))
+    }
+
+    fn descendants(self) -> impl Iterator<Item = Element<'a>> {
+        self.elements(self.arena.descendants(self.id))
+    }
+
+    /// The elements before this one in its parent, closest first
+    fn preceding_siblings(self) -> impl Iterator<Item = Element<'a>> {
+        self.elements(self.arena.preceding_siblings(self.id))
+    }
+
+    /// The elements after this one in its parent, closest first
+    fn following_siblings(self) -> impl Iterator<Item = Element<'a>> {
+        self.elements(self.arena.following_siblings(self.id))
+    }
+}
+
+/// How an element relates to the one matched by the previous query of a [HCombinedQuery]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HChildType {
+    /// The element is a child of the previous one (`a > b`)
     Direct,
+    /// The element is a descendant of the previous one (`a b`)
     Indirect,
+    /// The element comes right after the previous one, in the same parent (`a + b`)
+    Adjacent,
+    /// The element comes after the previous one, in the same parent (`a ~ b`)
+    Sibling,
 }
 
+/// A chain of queries, each matching a relative (child, descendant or sibling) of the element matched by the previous one.
+/// Ex: `ul > li a` is `ul`, followed by `li` as a direct child, followed by `a` as an indirect one
 #[derive(Debug)]
-pub struct HCombinedQuery<const N: usize> {
+pub struct HCombinedQuery {
     root: HQuery,
-    children: [(HQuery, HChildType); N],
+    children: Vec<(HQuery, HChildType)>,
 }
 
-impl<const N: usize> HCombinedQuery<N> {}
+impl HCombinedQuery {
+    /// Creates a combined query, starting from its outermost element
+    pub fn new(root: HQuery) -> HCombinedQuery {
+        HCombinedQuery {
+            root,
+            children: vec![],
+        }
+    }
 
-#[derive(Debug)]
+    /// Adds a query on the relatives of the last element of the chain
+    pub fn then(mut self, child_type: HChildType, query: HQuery) -> HCombinedQuery {
+        self.children.push((query, child_type));
+        self
+    }
+
+    /// Whether an element matches the last query of the chain, with its ancestors matching the rest
+    pub fn matches(&self, html_node: &HTMLNode) -> bool {
+        self.matches_in(html_node, QuirksMode::NoQuirks)
+    }
+
+    /// The query on the element this combined query selects, the last of the chain
+    fn subject(&self) -> &HQuery {
+        self.children.last().map_or(&self.root, |(query, _)| query)
+    }
+
+    /// The specificity of this query, the sum of that of each of its queries
+    pub fn specificity(&self) -> HSpecificity {
+        self.children
+            .iter()
+            .fold(self.root.specificity(), |total, (query, _)| {
+                total + query.specificity()
+            })
+    }
+
+    /// Same as [matches](HCombinedQuery::matches), in a document rendered in the given mode
+    pub fn matches_in(&self, html_node: &HTMLNode, mode: QuirksMode) -> bool {
+        html_node.read(|tree, id| self.matches_element(Element::new(&tree.arena, id), mode))
+    }
+
+    /// Same as [matches_in](HCombinedQuery::matches_in), on an element of a locked tree
+    fn matches_element(&self, element: Element, mode: QuirksMode) -> bool {
+        self.matches_from(self.children.len(), element, None, mode)
+    }
+
+    /// Returns every descendant of `root` matching this query, in document order
+    pub fn select(&self, root: &HTMLNode) -> Vec<HTMLNodeRef> {
+        root.query_selector_all(self)
+    }
+
+    /// Whether an element matches this query as a relative selector of `:has()`,
+    /// its outermost element being related to `scope` as `child_type` describes
+    fn matches_relative(
+        &self,
+        element: Element,
+        scope: Element,
+        child_type: HChildType,
+        mode: QuirksMode,
+    ) -> bool {
+        self.matches_from(
+            self.children.len(),
+            element,
+            Some((scope, child_type)),
+            mode,
+        )
+    }
+
+    /// Matches an element against the query at `index` of the chain (0 being the root),
+    /// then its ancestors against the previous ones.
+    /// If an anchor is given, the root element must also be related to it
+    fn matches_from(
+        &self,
+        index: usize,
+        element: Element,
+        anchor: Option<(Element, HChildType)>,
+        mode: QuirksMode,
+    ) -> bool {
+        if index == 0 {
+            return self.root.matches_element(element, mode)
+                && anchor.is_none_or(|(scope, child_type)| {
+                    related(child_type, element, |x| x.id == scope.id)
+                });
+        }
+
+        let (query, child_type) = &self.children[index - 1];
+
+        query.matches_element(element, mode)
+            && related(*child_type, element, |x| {
+                self.matches_from(index - 1, x, anchor, mode)
+            })
+    }
+}
+
+/// Whether the element `element` is related to, as described by `child_type`, satisfies the predicate.
+/// Ex: with [Direct](HChildType::Direct), whether its parent does
+fn related<F>(child_type: HChildType, element: Element, predicate: F) -> bool
+where
+    F: Fn(Element) -> bool,
+{
+    match child_type {
+        HChildType::Direct => element.parent().is_some_and(predicate),
+        HChildType::Indirect => element.ancestors().any(predicate),
+        HChildType::Adjacent => element.preceding_siblings().next().is_some_and(predicate),
+        HChildType::Sibling => element.preceding_siblings().any(predicate),
+    }
+}
+
+/// A comma-separated list of combined queries, matching the elements any of them matches.
+/// Ex: `h1, h2 + p`
+#[derive(Debug, Default)]
+pub struct HQueryList {
+    queries: Vec<HCombinedQuery>,
+}
+
+impl HQueryList {
+    /// Creates an empty list, which matches nothing
+    pub fn new() -> HQueryList {
+        HQueryList::default()
+    }
+
+    /// Adds a query to the list
+    pub fn or(mut self, query: HCombinedQuery) -> HQueryList {
+        self.queries.push(query);
+        self
+    }
+
+    /// Whether an element matches any query of the list
+    pub fn matches(&self, html_node: &HTMLNode) -> bool {
+        self.matches_in(html_node, QuirksMode::NoQuirks)
+    }
+
+    /// The specificity of the most specific query of the list
+    pub fn specificity(&self) -> HSpecificity {
+        self.queries
+            .iter()
+            .map(HCombinedQuery::specificity)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// The specificity the list selects an element with, in a document rendered in the given mode:
+    /// that of the most specific query matching it. None if no query matches it
+    pub fn specificity_for(&self, html_node: &HTMLNode, mode: QuirksMode) -> Option<HSpecificity> {
+        html_node.read(|tree, id| {
+            let element = Element::new(&tree.arena, id);
+
+            self.queries
+                .iter()
+                .filter(|x| x.matches_element(element, mode))
+                .map(HCombinedQuery::specificity)
+                .max()
+        })
+    }
+
+    /// Same as [matches](HQueryList::matches), in a document rendered in the given mode
+    pub fn matches_in(&self, html_node: &HTMLNode, mode: QuirksMode) -> bool {
+        html_node.read(|tree, id| self.matches_element(Element::new(&tree.arena, id), mode))
+    }
+
+    /// Same as [matches_in](HQueryList::matches_in), on an element of a locked tree
+    fn matches_element(&self, element: Element, mode: QuirksMode) -> bool {
+        self.queries
+            .iter()
+            .any(|x| x.matches_element(element, mode))
+    }
+
+    /// Returns every descendant of `root` matching any query of the list, in document order
+    pub fn select(&self, root: &HTMLNode) -> Vec<HTMLNodeRef> {
+        root.query_selector_all(self)
+    }
+}
+
+/// Compiled rules, each made of a list of queries, matched all at once against every element of a tree.
+///
+/// Queries are sorted into buckets by what the element they select must have, looking at its id, then its first class, then its tag name.
+/// Each element is only checked against the queries of the buckets it falls in, and the ones that could not be sorted
+#[derive(Debug, Default)]
+pub struct HSelectorSet {
+    /// Every query, along with the rule it belongs to
+    queries: Vec<(HCombinedQuery, usize)>,
+
+    /// Number of rules added
+    rules: usize,
+
+    /// Queries by the id of the element they select, ASCII-lowercased for quirks mode
+    by_id: HashMap<String, Vec<usize>>,
+
+    /// Queries by the first class of the element they select, ASCII-lowercased for quirks mode
+    by_class: HashMap<String, Vec<usize>>,
+
+    /// Queries by the tag name of the element they select
+    by_tag: HashMap<String, Vec<usize>>,
+
+    /// Queries selecting elements with no id, class or tag name
+    universal: Vec<usize>,
+}
+
+impl HSelectorSet {
+    /// Creates a set without any rule
+    pub fn new() -> HSelectorSet {
+        HSelectorSet::default()
+    }
+
+    /// Adds a rule, selecting the elements any of the queries of the list matches.
+    /// Returns the index of the rule, in the order rules were added
+    pub fn add(&mut self, list: HQueryList) -> usize {
+        let rule = self.rules;
+        self.rules += 1;
+
+        for query in list.queries {
+            let index = self.queries.len();
+            let subject = query.subject();
+
+            let bucket = if let Some(id) = &subject.id {
+                self.by_id.entry(id.to_ascii_lowercase()).or_default()
+            } else if let Some(class) = subject.classes.first() {
+                self.by_class.entry(class.to_ascii_lowercase()).or_default()
+            } else if let Some(name) = &subject.name {
+                self.by_tag.entry(name.clone()).or_default()
+            } else {
+                &mut self.universal
+            };
+
+            bucket.push(index);
+            self.queries.push((query, rule));
+        }
+
+        rule
+    }
+
+    /// The number of rules in the set
+    pub fn len(&self) -> usize {
+        self.rules
+    }
+
+    /// Whether the set has no rule
+    pub fn is_empty(&self) -> bool {
+        self.rules == 0
+    }
+
+    /// The indexes of the rules selecting an element, in increasing order
+    pub fn matching(&self, html_node: &HTMLNode) -> Vec<usize> {
+        self.matching_in(html_node, QuirksMode::NoQuirks)
+    }
+
+    /// Same as [matching](HSelectorSet::matching), in a document rendered in the given mode
+    pub fn matching_in(&self, html_node: &HTMLNode, mode: QuirksMode) -> Vec<usize> {
+        html_node.read(|tree, id| self.matching_element(Element::new(&tree.arena, id), mode))
+    }
+
+    /// Same as [matching_in](HSelectorSet::matching_in), on an element of a locked tree
+    fn matching_element(&self, element: Element, mode: QuirksMode) -> Vec<usize> {
+        let data = element.data();
+        let mut candidates: Vec<usize> = self.universal.clone();
+        let mut add_bucket = |bucket: Option<&Vec<usize>>| {
+            if let Some(bucket) = bucket {
+                candidates.extend_from_slice(bucket);
+            }
+        };
+
+        if let Some(id) = data.attribute("id") {
+            add_bucket(self.by_id.get(&id.to_ascii_lowercase()));
+        }
+
+        if let Some(classes) = data.attribute("class") {
+            for class in classes.split(is_html_whitespace).filter(|x| !x.is_empty()) {
+                add_bucket(self.by_class.get(&class.to_ascii_lowercase()));
+            }
+        }
+
+        add_bucket(self.by_tag.get(&data.name));
+
+        // Buckets only narrow down the queries, which are then fully checked
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        let mut rules: Vec<usize> = candidates
+            .into_iter()
+            .map(|index| &self.queries[index])
+            .filter(|(query, _)| query.matches_element(element, mode))
+            .map(|(_, rule)| *rule)
+            .collect();
+
+        rules.dedup();
+        rules
+    }
+
+    /// Runs every rule over the scope, walking through it once.
+    /// Returns, for each rule, the elements it selects, in document order
+    pub fn select_all<Q>(&self, scope: &Q) -> Vec<Vec<HTMLNodeRef>>
+    where
+        Q: HQueryable + ?Sized,
+    {
+        let mode = scope.quirks_mode();
+        let mut selected: Vec<Vec<HTMLNodeRef>> = vec![vec![]; self.rules];
+
+        for node in scope.query_scope() {
+            for rule in self.matching_in(&node, mode) {
+                selected[rule].push(node.clone());
+            }
+        }
+
+        selected
+    }
+}
+
+/// Anything that can tell whether an element is selected: a single query, a combined query, or a list of them
+pub trait HSelector {
+    /// Whether the element is selected, in a document rendered in the given mode
+    fn matches_in(&self, html_node: &HTMLNode, mode: QuirksMode) -> bool;
+
+    /// Whether the element is selected, in a standards mode document
+    fn matches(&self, html_node: &HTMLNode) -> bool {
+        self.matches_in(html_node, QuirksMode::NoQuirks)
+    }
+
+    /// How specific the selector is, to decide which one wins when several select the same element
+    fn specificity(&self) -> HSpecificity;
+}
+
+impl HSelector for HQuery {
+    fn matches_in(&self, html_node: &HTMLNode, mode: QuirksMode) -> bool {
+        HQuery::matches_in(self, html_node, mode)
+    }
+
+    fn specificity(&self) -> HSpecificity {
+        HQuery::specificity(self)
+    }
+}
+
+impl HSelector for HCombinedQuery {
+    fn matches_in(&self, html_node: &HTMLNode, mode: QuirksMode) -> bool {
+        HCombinedQuery::matches_in(self, html_node, mode)
+    }
+
+    fn specificity(&self) -> HSpecificity {
+        HCombinedQuery::specificity(self)
+    }
+}
+
+impl HSelector for HQueryList {
+    fn matches_in(&self, html_node: &HTMLNode, mode: QuirksMode) -> bool {
+        HQueryList::matches_in(self, html_node, mode)
+    }
+
+    fn specificity(&self) -> HSpecificity {
+        HQueryList::specificity(self)
+    }
+}
+
+/// Anything queries can be run over: an element or a whole document
+pub trait HQueryable {
+    /// The elements searched by queries, in document order
+    fn query_scope(&self) -> Descendants;
+
+    /// The mode selectors are matched in. Standards mode, unless the whole document is known
+    fn quirks_mode(&self) -> QuirksMode {
+        QuirksMode::NoQuirks
+    }
+
+    /// Returns the first element matching the selector, in document order.
+    /// Stops looking through the tree as soon as it is found
+    fn query_selector<S>(&self, selector: &S) -> Option<HTMLNodeRef>
+    where
+        S: HSelector + ?Sized,
+    {
+        let mode = self.quirks_mode();
+        self.query_scope().find(|x| selector.matches_in(x, mode))
+    }
+
+    /// Returns every element matching the selector, once each, in document order
+    fn query_selector_all<S>(&self, selector: &S) -> Vec<HTMLNodeRef>
+    where
+        S: HSelector + ?Sized,
+    {
+        let mode = self.quirks_mode();
+        self.query_scope()
+            .filter(|x| selector.matches_in(x, mode))
+            .collect()
+    }
+}
+
+/// Searches the element's descendants, not the element itself
+impl HQueryable for HTMLNode {
+    fn query_scope(&self) -> Descendants {
+        self.descendants()
+    }
+}
+
+/// An error found by a query on a document
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum HQueryErr {
-    DuplicateId,
+    /// Several elements share an id, which must be unique in a document.
+    /// Holds where each of these elements was parsed, if they were
+    DuplicateId {
+        id: String,
+        positions: Vec<SourcePosition>,
+    },
+}
+
+impl Display for HQueryErr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HQueryErr::DuplicateId { id, positions } => {
+                write!(f, "duplicate id \"{}\"", id)?;
+
+                let positions: Vec<String> = positions.iter().map(|x| x.to_string()).collect();
+                if !positions.is_empty() {
+                    write!(f, " at {}", positions.join(", "))?;
+                }
+
+                Ok(())
+            }
+        }
+    }
 }
@@ -1,14 +1,17 @@
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::fmt::{Display, Formatter};
-use std::rc::{Rc, Weak};
+use crate::arena::{Arena, ElementData, NodeData, NodeId};
+use crate::attributes::Attributes;
+use crate::doctype::Doctype;
+use crate::serialize::{escape_text, write_attributes, NodeHtml, __RAW_TEXT};
+use crate::tree::{Tree, TreeCell};
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::{Arc, Weak};
 
 /// Some html tags are self-closing and do not absolutely need an ending Slash
 /// This is the case with `<br>`, for example (which can also be written `<br/>`)
 /// These elements cannot have children.
 pub const __SELF_CLOSED: &[&str] = &[
     "are", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
-    "track", "wbr", "command", "keygen", "menuitem", "frame", "!doctype",
+    "track", "wbr", "command", "keygen", "menuitem", "frame",
 ];
 
 #[derive(Debug, Clone)]
@@ -17,156 +20,491 @@ pub enum HTMLEnum {
     Text(String),
     Node(HTMLNodeRef),
     Comment(String),
+    Doctype(Doctype),
 }
 
 /// A Weak reference to a HTMLElement.
-/// Used by children to reference their parents, without actually owning them
-pub type HTMLNodeWeakRef = Weak<RefCell<HTMLNode>>;
+/// It does not keep the element's tree alive, and cannot be upgraded once nothing else does
+#[derive(Debug, Clone)]
+pub struct HTMLNodeWeakRef {
+    tree: Weak<TreeCell>,
+    id: NodeId,
+}
+
+impl HTMLNodeWeakRef {
+    /// A strong reference to the element, if its tree is still alive
+    pub fn upgrade(&self) -> Option<HTMLNodeRef> {
+        Some(HTMLNode {
+            tree: self.tree.upgrade()?,
+            id: self.id,
+        })
+    }
+}
 
 /// A Strong reference to a HTMLElement.
-/// Used by parents to reference their children.
-pub type HTMLNodeRef = Rc<RefCell<HTMLNode>>;
+/// Elements are handles already, so this is the same type
+pub type HTMLNodeRef = HTMLNode;
+
+/// A position in a parsed source, as a byte offset and as a line and column (in characters), both starting at 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourcePosition {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for SourcePosition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
 
-#[derive(Debug, Clone)]
 /// A HTML tag.
+///
+/// A cheap handle to an element stored in an [Arena], shared with every node it was ever linked to.
+/// Clones point to the same element, and compare equal. The arena is locked for the length of each call,
+/// so handles can be sent to, and used from, other threads.
+/// Linking nodes of two different arenas moves the smaller one into the other
+#[derive(Clone)]
 pub struct HTMLNode {
-    ///The name of this tag.
-    /// Ex: for a ```<div></div>```, `name` = "div"
-    pub name: String,
-
-    ///This tag's attributes
-    pub(crate) attributes: HashMap<String, Option<String>>,
-
-    ///This tag's contents, whether they be tags or text.
-    pub(crate) children: Vec<HTMLEnum>,
-
-    ///This tag's parent (as a weak reference)
-    pub(crate) parent: Option<HTMLNodeWeakRef>,
+    /// The tree this handle was made in, which may have been merged into another one since
+    tree: Arc<TreeCell>,
 
-    //A weak reference to the self, to pass around
-    weak_self: HTMLNodeWeakRef,
+    /// Where the element is in that tree
+    id: NodeId,
 }
 
 impl HTMLNode {
     //Create a reference to a new element
     pub fn new() -> HTMLNodeRef {
-        let elem = Rc::new(RefCell::new(HTMLNode {
-            name: String::default(),
-            attributes: HashMap::default(),
-            children: vec![],
-            parent: None,
-            weak_self: Default::default(),
-        }));
+        let mut arena = Arena::new();
+        let id = arena.new_element("");
 
-        elem.borrow_mut().weak_self = Rc::downgrade(&elem);
-        elem
+        HTMLNode::at(&TreeCell::new(arena), id)
+    }
+
+    /// A handle to an element of a tree
+    pub(crate) fn at(tree: &Arc<TreeCell>, id: NodeId) -> HTMLNode {
+        HTMLNode {
+            tree: tree.clone(),
+            id,
+        }
+    }
+
+    /// Runs `f` on the tree this element is in, given where the element is in it.
+    /// The tree stays locked until `f` returns, so `f` must not go through handles
+    pub(crate) fn read<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&Tree, NodeId) -> R,
+    {
+        let (tree, id) = TreeCell::read(&self.tree, self.id);
+        f(&tree, id)
+    }
+
+    /// Same as [read](HTMLNode::read), to edit the tree
+    pub(crate) fn write<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Tree, NodeId) -> R,
+    {
+        let (mut tree, id) = TreeCell::write(&self.tree, self.id);
+        f(&mut tree, id)
+    }
+
+    /// Same as [write](HTMLNode::write), first bringing the other elements into this element's tree.
+    /// `f` is also given where they are
+    fn write_with<F, R>(&self, others: &[&HTMLNode], f: F) -> R
+    where
+        F: FnOnce(&mut Tree, NodeId, &[NodeId]) -> R,
+    {
+        loop {
+            let (cell, id) = TreeCell::resolve(&self.tree, self.id);
+            let mut merged = false;
+
+            for other in others {
+                let (other_cell, _) = TreeCell::resolve(&other.tree, other.id);
+
+                if !Arc::ptr_eq(cell, other_cell) {
+                    TreeCell::merge(cell, other_cell);
+                    merged = true;
+                }
+            }
+
+            // Any merge may have moved this element as well
+            if merged {
+                continue;
+            }
+
+            let mut tree = cell.lock();
+
+            if cell.is_merged() {
+                continue;
+            }
+
+            // Nothing can leave a tree while it is locked, so the others are still there
+            let ids: Vec<NodeId> = others
+                .iter()
+                .map(|x| TreeCell::resolve(&x.tree, x.id).1)
+                .collect();
+
+            return f(&mut tree, id, &ids);
+        }
+    }
+
+    /// Gives this element back.
+    /// Elements used to sit behind a `RefCell`: every method takes `&self` now
+    #[allow(clippy::should_implement_trait)]
+    pub fn borrow(&self) -> &HTMLNode {
+        self
+    }
+
+    /// Same as [borrow](HTMLNode::borrow), elements being edited through `&self`
+    #[allow(clippy::should_implement_trait)]
+    pub fn borrow_mut(&self) -> &HTMLNode {
+        self
     }
 
     /// Gives a reference to this element
     pub fn reference(&self) -> Option<HTMLNodeRef> {
-        self.weak_self.upgrade()
+        Some(self.clone())
     }
 
     /// Gives a weak reference to this element
     pub fn weak_reference(&self) -> HTMLNodeWeakRef {
-        self.weak_self.clone()
+        let (cell, id) = TreeCell::resolve(&self.tree, self.id);
+
+        HTMLNodeWeakRef {
+            tree: Arc::downgrade(cell),
+            id,
+        }
     }
 
     /// Creates a copy of this node, with the same name and attributes.
     /// Children and parents are not copied.
     pub fn duplicate(&self) -> HTMLNodeRef {
-        let dup = Self::new();
-
-        let mut dup_borrow = dup.borrow_mut();
-        dup_borrow.name = self.name.clone();
-        dup_borrow.attributes = self.attributes.clone();
-        drop(dup_borrow);
-        //Cloning the parent reference would make no sense, as the parent wouldn't be pointing to a newly created element
-
-        dup
+        self.write(|tree, id| {
+            let element = element(tree, id);
+            let dup = NodeData::Element(ElementData {
+                name: element.name.clone(),
+                attributes: element.attributes.clone(),
+                source_position: None,
+            });
+
+            let dup = tree.arena.new_node(dup);
+            tree.node(dup)
+        })
     }
 
     /// Creates a copy of this node, with the same name, attributes, and (copy of its) children
     /// Its parent is not copied, and as such, the duplicate is an orphan
     pub fn duplicate_family(&self) -> HTMLNodeRef {
-        let replicant = self.duplicate();
-        let mut replicant_borrow = replicant.borrow_mut();
-
-        for child in &self.children {
-            match child {
-                HTMLEnum::Node(noderef) => {
-                    replicant_borrow.add_child(noderef.borrow().duplicate_family());
-                }
-                other => {
-                    replicant_borrow.add_children(vec![other.clone()]);
+        self.write(|tree, id| {
+            let replicant = tree.arena.duplicate(id);
+            let copies: Vec<NodeId> = std::iter::once(replicant)
+                .chain(tree.arena.descendants(replicant))
+                .collect();
+
+            // The copies were not parsed from anywhere
+            for copy in copies {
+                if let Some(element) = tree.arena.element_mut(copy) {
+                    element.source_position = None;
                 }
             }
-        }
 
-        drop(replicant_borrow);
-        replicant
+            tree.node(replicant)
+        })
     }
 
     /// Returns a strong reference to this element's parent
     pub fn parent(&self) -> Option<HTMLNodeRef> {
-        match &self.parent {
-            None => None,
-            Some(parent) => Weak::upgrade(&parent),
-        }
+        self.read(|tree, id| tree.arena.get(id).parent().map(|x| tree.node(x)))
     }
 
     //Edit the thing
-    pub fn attribute(&mut self, attribute: String, value: Option<String>) -> &HTMLNode {
-        //Attributes are only meant to be added / modified, not removed
-        self.attributes.insert(attribute, value);
+    pub fn attribute(&self, attribute: String, value: Option<String>) -> &HTMLNode {
+        let is_id = attribute.eq_ignore_ascii_case("id");
+
+        self.write(|tree, id| {
+            tree.edit_attributes(id, is_id, |attributes| attributes.set(attribute, value))
+        });
+
         self
     }
-    pub fn add_child(&mut self, child: HTMLNodeRef) -> &mut Self {
-        child.borrow_mut().orphanize();
-        child.borrow_mut().parent = Some(self.weak_self.clone());
-        self.children.push(HTMLEnum::Node(child));
-        self
+
+    /// Removes an attribute, returning its value if it was set
+    pub fn remove_attribute(&self, attribute: &str) -> Option<Option<String>> {
+        let is_id = attribute.eq_ignore_ascii_case("id");
+
+        self.write(|tree, id| {
+            tree.edit_attributes(id, is_id, |attributes| attributes.remove(attribute))
+        })
     }
-    pub fn add_children(&mut self, children: Vec<HTMLEnum>) -> &mut Self {
-        for child in children {
-            self.children.push(child);
-        }
+
+    /// Removes an attribute if it is set, or sets it without a value if it is not.
+    /// With `force`, the attribute is only ever set (`Some(true)`) or removed (`Some(false)`).
+    /// Returns whether the attribute is set afterwards
+    pub fn toggle_attribute(&self, attribute: &str, force: Option<bool>) -> bool {
+        let is_id = attribute.eq_ignore_ascii_case("id");
+
+        self.write(|tree, id| {
+            tree.edit_attributes(id, is_id, |attributes| {
+                let present = attributes.contains(attribute);
+
+                match (present, force) {
+                    (true, None | Some(false)) => {
+                        attributes.remove(attribute);
+                        false
+                    }
+                    (false, None | Some(true)) => {
+                        attributes.set(attribute.to_string(), None);
+                        true
+                    }
+                    (present, _) => present,
+                }
+            })
+        })
+    }
+
+    pub fn add_child(&self, child: HTMLNodeRef) -> &Self {
+        self.add_children(vec![HTMLEnum::Node(child)])
+    }
+    pub fn add_children(&self, children: Vec<HTMLEnum>) -> &Self {
+        self.insert_nodes(children, false, |_, id, _| Some((id, None)));
         self
     }
-    pub fn add_text<'x>(&'x mut self, text: String) -> &'x Self {
-        self.children.push(HTMLEnum::Text(text));
+    pub fn add_text(&self, text: String) -> &Self {
+        self.add_children(vec![HTMLEnum::Text(text)])
+    }
+
+    /// Inserts nodes before this node's first child
+    pub fn prepend(&self, children: Vec<HTMLEnum>) -> &Self {
+        self.insert_children(0, children)
+    }
+
+    /// Inserts nodes among this node's children, the first one ending up at `index`.
+    /// Elements are detached from their current parent first.
+    /// Panics if `index` is greater than the number of children, or if one of the nodes is this node or one of its ancestors
+    pub fn insert_children(&self, index: usize, children: Vec<HTMLEnum>) -> &Self {
+        self.insert_nodes(children, false, |tree, id, inserted| {
+            let count = tree.arena.children(id).count();
+            assert!(
+                index <= count,
+                "Insertion index {} is past the {} children of the node",
+                index,
+                count
+            );
+
+            // The children being moved leave their place
+            let next = tree
+                .arena
+                .children(id)
+                .skip(index)
+                .find(|x| !inserted.contains(x));
+
+            Some((id, next))
+        });
+
         self
     }
 
-    ///Disconnect this node from its parent
-    pub fn orphanize(&mut self) -> &mut Self {
-        match self.parent() {
-            None => self, //No parent, the job is done
-            Some(parent) => {
-                let mut parent_borrow = parent.borrow_mut();
-
-                match parent_borrow.children.iter().position(|x| match x {
-                    HTMLEnum::Node(reference) => {
-                        Weak::ptr_eq(&Rc::downgrade(reference), &self.weak_self)
-                    }
-                    _ => false,
-                }) {
-                    None => {
-                        panic!("A child has been disowned. This should not happen");
+    /// Inserts nodes under the parent `place` gives, before the child it gives or last,
+    /// detaching this node too if `replace` is set. Does nothing if `place` gives no parent.
+    /// `place` is given where the elements being inserted are. Nothing is moved before every check passed
+    fn insert_nodes<F>(&self, nodes: Vec<HTMLEnum>, replace: bool, place: F)
+    where
+        F: FnOnce(&Tree, NodeId, &[NodeId]) -> Option<(NodeId, Option<NodeId>)>,
+    {
+        let elements: Vec<&HTMLNode> = nodes
+            .iter()
+            .filter_map(|x| match x {
+                HTMLEnum::Node(node) => Some(node),
+                _ => None,
+            })
+            .collect();
+
+        self.write_with(&elements, |tree, id, inserted| {
+            let (parent, next) = match place(tree, id, inserted) {
+                None => return,
+                Some(place) => place,
+            };
+
+            for node in inserted {
+                assert!(
+                    !tree.arena.is_inclusive_ancestor(*node, parent),
+                    "A node cannot be inserted into itself or into one of its descendants"
+                );
+            }
+
+            if replace {
+                tree.detach(id);
+            }
+
+            let mut inserted = inserted.iter();
+
+            for node in &nodes {
+                let child = match node {
+                    HTMLEnum::Node(_) => *inserted.next().expect("Every element was resolved"),
+                    HTMLEnum::Text(text) => tree.arena.new_node(NodeData::Text(text.clone())),
+                    HTMLEnum::Comment(comment) => {
+                        tree.arena.new_node(NodeData::Comment(comment.clone()))
                     }
-                    Some(index) => {
-                        parent_borrow.children.remove(index);
-                        self.parent = None;
-                        return self;
+                    HTMLEnum::Doctype(doctype) => {
+                        tree.arena.new_node(NodeData::Doctype(doctype.clone()))
                     }
-                }
+                };
+
+                tree.insert(parent, child, next);
             }
+        })
+    }
+
+    /// Removes an element from this node's children.
+    /// Returns whether it was one of them
+    pub fn remove_child(&self, child: &HTMLNodeRef) -> bool {
+        self.write_with(&[child], |tree, id, child| {
+            let is_child = tree.arena.get(child[0]).parent() == Some(id);
+
+            if is_child {
+                tree.detach(child[0]);
+            }
+
+            is_child
+        })
+    }
+
+    /// Replaces all of this node's children with a single text node, or with nothing if the text is empty
+    pub fn set_text_content(&self, text: &str) -> &Self {
+        self.write(|tree, id| {
+            let children: Vec<NodeId> = tree.arena.children(id).collect();
+
+            // Takes the elements being replaced out of the id index
+            for child in children {
+                tree.detach(child);
+            }
+
+            if !text.is_empty() {
+                let text = tree.arena.new_text(text);
+                tree.insert(id, text, None);
+            }
+        });
+
+        self
+    }
+
+    /// Detaches every child of this node, and gives them back
+    pub(crate) fn take_children(&self) -> Vec<HTMLEnum> {
+        self.write(|tree, id| {
+            let children: Vec<NodeId> = tree.arena.children(id).collect();
+
+            children
+                .into_iter()
+                .map(|child| {
+                    tree.detach(child);
+                    child_enum(tree, child)
+                })
+                .collect()
+        })
+    }
+
+    /// Inserts nodes in this node's parent, right before it, like `ChildNode.before()` in the DOM.
+    /// This node may be among them, and then ends up in their midst. Does nothing if it has no parent.
+    /// To insert children before one of them instead, use [insert_children](HTMLNode::insert_children)
+    /// with its [index_in_parent](HTMLNode::index_in_parent)
+    pub fn insert_before(&self, nodes: Vec<HTMLEnum>) -> &Self {
+        self.insert_nodes(nodes, false, |tree, id, inserted| {
+            let parent = tree.arena.get(id).parent()?;
+            let next = std::iter::once(id)
+                .chain(tree.arena.following_siblings(id))
+                .find(|x| !inserted.contains(x));
+
+            Some((parent, next))
+        });
+
+        self
+    }
+
+    /// Inserts nodes in this node's parent, right after it, like `ChildNode.after()` in the DOM.
+    /// This node may be among them, and then ends up in their midst. Does nothing if it has no parent
+    pub fn insert_after(&self, nodes: Vec<HTMLEnum>) -> &Self {
+        self.insert_nodes(nodes, false, |tree, id, inserted| {
+            let parent = tree.arena.get(id).parent()?;
+            let next = tree
+                .arena
+                .following_siblings(id)
+                .find(|x| !inserted.contains(x));
+
+            Some((parent, next))
+        });
+
+        self
+    }
+
+    /// Puts nodes in place of this one in its parent, leaving this node detached,
+    /// like `ChildNode.replaceWith()` in the DOM. If this node is among them, it stays in their midst.
+    /// Does nothing if it has no parent
+    pub fn replace_with(&self, nodes: Vec<HTMLEnum>) -> &Self {
+        let is_self = |x: &HTMLEnum| matches!(x, HTMLEnum::Node(node) if node == self);
+
+        // This node stays in its parent, so the others only have to go before it
+        if nodes.iter().any(is_self) {
+            return self.insert_before(nodes);
         }
+
+        self.insert_nodes(nodes, true, |tree, id, inserted| {
+            let parent = tree.arena.get(id).parent()?;
+            let next = tree
+                .arena
+                .following_siblings(id)
+                .find(|x| !inserted.contains(x));
+
+            Some((parent, next))
+        });
+
+        self
+    }
+
+    /// Replaces this node with its children in its parent, leaving it detached and empty.
+    /// Does nothing if it has no parent
+    pub fn unwrap(&self) -> &Self {
+        self.write(|tree, id| {
+            if let Some(parent) = tree.arena.get(id).parent() {
+                let children: Vec<NodeId> = tree.arena.children(id).collect();
+
+                for child in children {
+                    tree.insert(parent, child, Some(id));
+                }
+
+                tree.detach(id);
+            }
+        });
+
+        self
+    }
+
+    ///Disconnect this node from its parent
+    pub fn orphanize(&self) -> &Self {
+        self.write(|tree, id| tree.detach(id));
+        self
     }
 
     //Getter methods
-    pub fn name(&self) -> &str {
-        &self.name
+    pub fn name(&self) -> String {
+        self.read(|tree, id| element(tree, id).name.clone())
+    }
+
+    /// Renames this element
+    pub fn set_name(&self, name: &str) -> &Self {
+        self.write(|tree, id| element_mut(tree, id).name = name.to_string());
+        self
+    }
+
+    /// Where this element was parsed from, if it was
+    pub fn source_position(&self) -> Option<SourcePosition> {
+        self.read(|tree, id| element(tree, id).source_position)
+    }
+
+    /// Records where this element was parsed from
+    pub fn set_source_position(&self, position: SourcePosition) {
+        self.write(|tree, id| element_mut(tree, id).source_position = Some(position));
     }
 
     /// We return an option to an option because
@@ -174,67 +512,203 @@ impl HTMLNode {
     /// If the attribute appears with no value, the result is Some(None)
     /// If the attribute appears with a value, the result is Some(value)
     pub fn get_attribute(&self, name: &str) -> Option<Option<String>> {
-        match self
-            .attributes
-            .iter()
-            .find(|(key, _attribute)| key.to_string() == name.to_string())
-        {
-            Some((_key, val)) => return Some(val.clone()),
-            None => {
-                return None;
-            }
-        }
+        self.read(|tree, id| {
+            element(tree, id)
+                .attributes
+                .get(name)
+                .map(|value| value.map(String::from))
+        })
     }
-    pub fn children(&self) -> &Vec<HTMLEnum> {
-        &self.children
+
+    /// A copy of this tag's attributes, in the order they were set
+    pub fn attributes(&self) -> Attributes {
+        self.read(|tree, id| element(tree, id).attributes.clone())
+    }
+
+    /// This tag's contents. Elements are handles to the children themselves, text and comments are copies
+    pub fn children(&self) -> Vec<HTMLEnum> {
+        self.read(|tree, id| {
+            tree.arena
+                .children(id)
+                .map(|x| child_enum(tree, x))
+                .collect()
+        })
     }
     pub fn self_closing(&self) -> bool {
-        __SELF_CLOSED.iter().find(|&&x| x == self.name).is_some()
+        __SELF_CLOSED.contains(&self.name().as_str())
+    }
+    /// Whether this element contains raw text, which is never escaped (`<script>`, `<style>`)
+    pub fn raw_text(&self) -> bool {
+        __RAW_TEXT.contains(&self.name().as_str())
     }
 
     // Returns the chain of parents of this node, starting from the closest
     pub fn parent_chain(&self) -> Vec<HTMLNodeRef> {
-        let mut result = vec![];
+        self.read(|tree, id| tree.arena.ancestors(id).map(|x| tree.node(x)).collect())
+    }
 
-        let mut x: HTMLNodeRef;
+    /// The position of this node among its parent's children, text and comments included
+    pub fn index_in_parent(&self) -> Option<usize> {
+        self.read(|tree, id| {
+            tree.arena.get(id).parent()?;
+            Some(tree.arena.preceding_siblings(id).count())
+        })
+    }
 
-        if let Some(parent) = self.parent() {
-            x = parent.clone();
-            result.push(parent);
-        } else {
-            return result;
-        }
+    /// The node right after this one in its parent, whether it is an element, text or a comment
+    pub fn next_sibling(&self) -> Option<HTMLEnum> {
+        self.read(|tree, id| {
+            let next = tree.arena.get(id).next_sibling()?;
+            Some(child_enum(tree, next))
+        })
+    }
 
-        loop {
-            let par = x.borrow().parent();
+    /// The node right before this one in its parent, whether it is an element, text or a comment
+    pub fn previous_sibling(&self) -> Option<HTMLEnum> {
+        self.read(|tree, id| {
+            let previous = tree.arena.get(id).previous_sibling()?;
+            Some(child_enum(tree, previous))
+        })
+    }
 
-            if let Some(parent) = par {
-                x = parent.clone();
-                result.push(parent);
-            } else {
-                break;
-            }
-        }
+    /// The first element after this one in its parent, skipping text and comments
+    pub fn next_element_sibling(&self) -> Option<HTMLNodeRef> {
+        self.read(|tree, id| first_element(tree, tree.arena.following_siblings(id)))
+    }
+
+    /// The first element before this one in its parent, skipping text and comments
+    pub fn previous_element_sibling(&self) -> Option<HTMLNodeRef> {
+        self.read(|tree, id| first_element(tree, tree.arena.preceding_siblings(id)))
+    }
 
-        result
+    /// The concatenated text of every descendant text node, in document order
+    pub fn text_content(&self) -> String {
+        self.read(|tree, id| tree.arena.text_content(id))
+    }
+
+    /// Lazily iterates over the elements under this node, in document order
+    pub fn descendants(&self) -> Descendants {
+        Descendants::new(self.read(element_children))
     }
 
     // Returns the chain of HTMLElement children of this node, in a breadth-first order
     // Useful for quickly looking through nodes
     pub fn rec_html_children(&self) -> Vec<HTMLNodeRef> {
-        let mut result = vec![];
+        self.read(|tree, id| {
+            tree.arena
+                .descendants(id)
+                .filter(|x| tree.arena.element(*x).is_some())
+                .map(|x| tree.node(x))
+                .collect()
+        })
+    }
+}
 
-        for child in &self.children {
-            match child {
-                HTMLEnum::Node(html) => {
-                    result.push(html.clone());
-                    result.append(&mut html.borrow().rec_html_children());
-                }
-                _ => {}
+impl Default for HTMLNode {
+    fn default() -> HTMLNode {
+        HTMLNode::new()
+    }
+}
+
+/// Handles are equal when they point to the same element
+impl PartialEq for HTMLNode {
+    fn eq(&self, other: &HTMLNode) -> bool {
+        loop {
+            let (cell, id) = TreeCell::resolve(&self.tree, self.id);
+            let (other_cell, other_id) = TreeCell::resolve(&other.tree, other.id);
+
+            if Arc::ptr_eq(cell, other_cell) {
+                return id == other_id;
+            }
+
+            // Elements of different trees are different, unless this one was just merged into the other tree
+            if !cell.is_merged() {
+                return false;
             }
         }
+    }
+}
+
+impl Eq for HTMLNode {}
+
+impl Debug for HTMLNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HTMLNode")
+            .field("name", &self.name())
+            .field("attributes", &self.attributes())
+            .field("children", &self.children())
+            .finish()
+    }
+}
+
+/// The element a handle points to
+fn element(tree: &Tree, id: NodeId) -> &ElementData {
+    tree.arena
+        .element(id)
+        .expect("Handles only point to elements")
+}
+
+/// Same as [element], to edit it
+fn element_mut(tree: &mut Tree, id: NodeId) -> &mut ElementData {
+    tree.arena
+        .element_mut(id)
+        .expect("Handles only point to elements")
+}
+
+/// A child of a node, as a handle if it is an element, or a copy otherwise
+fn child_enum(tree: &Tree, id: NodeId) -> HTMLEnum {
+    match &tree.arena[id] {
+        NodeData::Element(_) => HTMLEnum::Node(tree.node(id)),
+        NodeData::Text(text) => HTMLEnum::Text(text.clone()),
+        NodeData::Comment(comment) => HTMLEnum::Comment(comment.clone()),
+        NodeData::Doctype(doctype) => HTMLEnum::Doctype(doctype.clone()),
+    }
+}
+
+/// The first element among the given nodes, skipping text and comments
+fn first_element<I>(tree: &Tree, mut nodes: I) -> Option<HTMLNodeRef>
+where
+    I: Iterator<Item = NodeId>,
+{
+    nodes
+        .find(|x| tree.arena.element(*x).is_some())
+        .map(|x| tree.node(x))
+}
+
+/// The children of a node that are elements
+fn element_children(tree: &Tree, id: NodeId) -> Vec<HTMLNodeRef> {
+    tree.arena
+        .children(id)
+        .filter(|x| tree.arena.element(*x).is_some())
+        .map(|x| tree.node(x))
+        .collect()
+}
+
+/// A depth-first iterator over elements and their descendants, in document order.
+/// Children are only looked at once their parent has been reached
+pub struct Descendants {
+    /// The elements left to visit, the next one last
+    stack: Vec<HTMLNodeRef>,
+}
+
+impl Descendants {
+    /// Iterates over the given elements, each followed by its descendants
+    pub fn new(roots: Vec<HTMLNodeRef>) -> Descendants {
+        let mut stack = roots;
+        stack.reverse();
+        Descendants { stack }
+    }
+}
 
-        return result;
+impl Iterator for Descendants {
+    type Item = HTMLNodeRef;
+
+    fn next(&mut self) -> Option<HTMLNodeRef> {
+        let node = self.stack.pop()?;
+        let children = node.read(element_children);
+
+        self.stack.extend(children.into_iter().rev());
+        Some(node)
     }
 }
 
@@ -247,101 +721,99 @@ pub trait PrettyPrintable {
 
 impl PrettyPrintable for HTMLEnum {
     fn pretty_fmt_rec(&self, depth: usize) -> String {
-        let mut buf = String::new();
-
         match &self {
-            HTMLEnum::Text(t) => {
-                buf += &format!(
-                    "{}",
-                    t.lines()
-                        .map(|x| format!("{}{}\n", "\t".repeat(depth), &x))
-                        .collect::<Vec<String>>()
-                        .join("\n")
-                );
-            }
-            HTMLEnum::Node(elem) => {
-                buf += &elem.borrow().pretty_fmt_rec(depth);
-            }
-            HTMLEnum::Comment(t) => {
-                buf += &"\t".repeat(depth);
-                buf += &format!("<!--{}-->\n", t);
-            }
+            HTMLEnum::Text(t) => pretty_fmt_text(t, depth),
+            HTMLEnum::Node(elem) => elem.pretty_fmt_rec(depth),
+            HTMLEnum::Comment(t) => pretty_fmt_comment(t, depth),
+            HTMLEnum::Doctype(doctype) => pretty_fmt_doctype(doctype, depth),
         }
-        buf
     }
 }
 
 impl PrettyPrintable for HTMLNode {
     fn pretty_fmt_rec(&self, depth: usize) -> String {
-        let mut buf = String::new();
-
-        buf += &format!("{}", "\t".repeat(depth));
-        buf += &format!("<{}", self.name);
+        self.read(|tree, id| pretty_fmt_node(&tree.arena, id, depth))
+    }
+}
 
-        for (attr, val) in &self.attributes {
-            buf += &format!("{}={}", attr, val.clone().unwrap_or(String::from(r#""""#)))
-        }
+fn pretty_fmt_text(t: &str, depth: usize) -> String {
+    escape_text(t)
+        .lines()
+        .map(|x| format!("{}{}\n", "\t".repeat(depth), &x))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
 
-        if self.self_closing() {
-            buf += ">";
-        } else {
-            buf += ">";
+fn pretty_fmt_comment(t: &str, depth: usize) -> String {
+    format!("{}<!--{}-->\n", "\t".repeat(depth), t)
+}
 
-            for child in &self.children {
-                buf += "\n";
-                buf += &child.pretty_fmt_rec(depth + 1);
-            }
+fn pretty_fmt_doctype(doctype: &Doctype, depth: usize) -> String {
+    format!("{}{}\n", "\t".repeat(depth), doctype)
+}
 
-            if !self.children.is_empty() {
-                buf += "\n";
-                buf += &"\t".repeat(depth);
-            }
+/// Formats a node of an arena and everything under it, one node per line
+fn pretty_fmt_node(arena: &Arena, id: NodeId, depth: usize) -> String {
+    let element = match &arena[id] {
+        NodeData::Element(element) => element,
+        NodeData::Text(t) => return pretty_fmt_text(t, depth),
+        NodeData::Comment(t) => return pretty_fmt_comment(t, depth),
+        NodeData::Doctype(doctype) => return pretty_fmt_doctype(doctype, depth),
+    };
+
+    let mut buf = String::new();
+    let attributes = element
+        .attributes
+        .iter()
+        .map(|(attribute, value)| (attribute.as_str(), value.as_deref()));
+
+    buf += &"\t".repeat(depth);
+    buf += &format!("<{}", element.name);
+    write_attributes(&mut buf, attributes).expect("Writing to a String never fails");
+    buf += ">";
+
+    if !__SELF_CLOSED.contains(&element.name.as_str()) {
+        let raw_text = __RAW_TEXT.contains(&element.name.as_str());
+
+        for child in arena.children(id) {
+            buf += "\n";
+            buf += &match &arena[child] {
+                NodeData::Text(t) if raw_text => format!("{}{}", "\t".repeat(depth + 1), t),
+                _ => pretty_fmt_node(arena, child, depth + 1),
+            };
+        }
 
-            buf += &format!("</{}>", self.name);
+        if arena.get(id).first_child().is_some() {
+            buf += "\n";
+            buf += &"\t".repeat(depth);
         }
 
-        buf
+        buf += &format!("</{}>", element.name);
     }
+
+    buf
 }
 
 impl Display for HTMLEnum {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             HTMLEnum::Text(str) => {
-                write!(f, "{}", str)
+                write!(f, "{}", escape_text(str))
             }
             HTMLEnum::Node(elem) => {
-                write!(f, "{}", elem.borrow().pretty_fmt())
+                write!(f, "{}", elem)
             }
             HTMLEnum::Comment(str) => {
-                write!(f, "<!--{}-->\n", str)
+                write!(f, "<!--{}-->", str)
+            }
+            HTMLEnum::Doctype(doctype) => {
+                write!(f, "{}", doctype)
             }
         }
     }
 }
 impl Display for HTMLNode {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "<{}", self.name)?;
-
-        for (attribute, value) in &self.attributes {
-            write!(
-                f,
-                " {}=\"{}\"",
-                attribute,
-                value.clone().unwrap_or(r#""""#.to_string())
-            )?;
-        }
-
-        if self.self_closing() {
-            write!(f, "/>")
-        } else {
-            write!(f, ">")?;
-
-            for child in &self.children {
-                write!(f, "{}", child)?;
-            }
-
-            write!(f, "</{}>", self.name)
-        }
+        self.read(|tree, id| write!(f, "{}", NodeHtml::new(&tree.arena, id)))
     }
 }
@@ -1,3 +1,10 @@
+pub mod arena;
+pub mod attributes;
+pub mod borrowed;
+pub mod doctype;
 pub mod document;
+pub mod frozen;
 pub mod hqueries;
 pub mod html_elements;
+pub mod serialize;
+mod tree;
@@ -0,0 +1,141 @@
+use crate::arena::{Arena, NodeData, NodeId};
+use crate::html_elements::__SELF_CLOSED;
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter, Write};
+
+/// Elements whose contents are raw text.
+/// Their text is written back as is, since character references are not decoded inside them.
+pub const __RAW_TEXT: &[&str] = &["script", "style"];
+
+/// Escapes a text node so it can be written back between tags.
+/// `&`, `<`, `>` and non-breaking spaces are replaced by their character reference
+pub fn escape_text(text: &str) -> Cow<'_, str> {
+    escape(text, false)
+}
+
+/// Escapes an attribute value so it can be written back between double quotes.
+/// `&`, `"`, `<`, `>` and non-breaking spaces are replaced by their character reference
+pub fn escape_attribute(value: &str) -> Cow<'_, str> {
+    escape(value, true)
+}
+
+/// A child of an element being serialized
+pub(crate) enum Child<'a> {
+    /// Text, escaped unless the element holds raw text
+    Text(&'a str),
+    /// Any other node, written as it displays
+    Other(&'a dyn Display),
+}
+
+/// Writes an element and its children as html.
+/// Void elements are self-closed, and the text of raw text elements is written as is,
+/// since the parser does not decode it
+pub(crate) fn write_element<'a, W: Write>(
+    w: &mut W,
+    name: &str,
+    attributes: impl Iterator<Item = (&'a str, Option<&'a str>)>,
+    children: impl Iterator<Item = Child<'a>>,
+) -> std::fmt::Result {
+    write!(w, "<{}", name)?;
+    write_attributes(w, attributes)?;
+
+    if __SELF_CLOSED.contains(&name) {
+        return write!(w, "/>");
+    }
+
+    write!(w, ">")?;
+    let raw_text = __RAW_TEXT.contains(&name);
+
+    for child in children {
+        match child {
+            Child::Text(text) if raw_text => write!(w, "{}", text)?,
+            Child::Text(text) => write!(w, "{}", escape_text(text))?,
+            Child::Other(node) => write!(w, "{}", node)?,
+        }
+    }
+
+    write!(w, "</{}>", name)
+}
+
+/// A node of an arena, displayed as html along with everything under it
+pub(crate) struct NodeHtml<'a> {
+    arena: &'a Arena,
+    id: NodeId,
+}
+
+impl<'a> NodeHtml<'a> {
+    pub(crate) fn new(arena: &'a Arena, id: NodeId) -> NodeHtml<'a> {
+        NodeHtml { arena, id }
+    }
+}
+
+impl Display for NodeHtml<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let arena = self.arena;
+
+        match &arena[self.id] {
+            NodeData::Text(text) => write!(f, "{}", escape_text(text)),
+            NodeData::Comment(comment) => write!(f, "<!--{}-->", comment),
+            NodeData::Doctype(doctype) => write!(f, "{}", doctype),
+            NodeData::Element(element) => {
+                let attributes = element
+                    .attributes
+                    .iter()
+                    .map(|(attribute, value)| (attribute.as_str(), value.as_deref()));
+                let nodes: Vec<NodeHtml> = arena
+                    .children(self.id)
+                    .map(|id| NodeHtml::new(arena, id))
+                    .collect();
+                let children = nodes.iter().map(|child| match &arena[child.id] {
+                    NodeData::Text(text) => Child::Text(text),
+                    _ => Child::Other(child),
+                });
+
+                write_element(f, &element.name, attributes, children)
+            }
+        }
+    }
+}
+
+/// Writes attributes as they appear in an opening tag, each with a leading space.
+/// Valueless attributes are written by name only
+pub(crate) fn write_attributes<'a, W: Write>(
+    w: &mut W,
+    attributes: impl Iterator<Item = (&'a str, Option<&'a str>)>,
+) -> std::fmt::Result {
+    for (attribute, value) in attributes {
+        match value {
+            None => write!(w, " {}", attribute)?,
+            Some(value) => write!(w, " {}=\"{}\"", attribute, escape_attribute(value))?,
+        }
+    }
+
+    Ok(())
+}
+
+fn escape(text: &str, attribute: bool) -> Cow<'_, str> {
+    let needs_escaping = |c: char| match c {
+        '&' | '<' | '>' | '\u{a0}' => true,
+        '"' => attribute,
+        _ => false,
+    };
+
+    if !text.contains(needs_escaping) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut escaped = String::with_capacity(text.len() + 8);
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\u{a0}' => escaped.push_str("&nbsp;"),
+            '"' if attribute => escaped.push_str("&quot;"),
+            other => escaped.push(other),
+        }
+    }
+
+    Cow::Owned(escaped)
+}
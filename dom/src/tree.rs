@@ -0,0 +1,248 @@
+use crate::arena::{Arena, NodeId};
+use crate::attributes::Attributes;
+use crate::html_elements::HTMLNode;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak};
+
+/// Maps ids to the elements of a document that have them, in no particular order
+pub(crate) type IdIndex = HashMap<String, Vec<NodeId>>;
+
+/// The arena behind a set of [HTMLNode] handles, along with the id index of the documents it holds
+#[derive(Debug, Default)]
+pub(crate) struct Tree {
+    pub(crate) arena: Arena,
+
+    /// The id index of each document, by the `<html>` element it is rooted at.
+    /// Elements belong to the document rooted at the topmost of their ancestors, if there is one
+    documents: HashMap<NodeId, IdIndex>,
+
+    /// The cell this tree is locked in, to give out handles
+    cell: Weak<TreeCell>,
+}
+
+/// A [Tree] shared between handles, possibly on several threads.
+/// Once merged into another tree, it stays empty and only forwards to it
+#[derive(Debug)]
+pub(crate) struct TreeCell {
+    tree: RwLock<Tree>,
+
+    /// The cell this tree was merged into, along with the offset its handles are shifted by there
+    merged: OnceLock<(Arc<TreeCell>, usize)>,
+}
+
+impl TreeCell {
+    /// Shares an arena between handles
+    pub(crate) fn new(arena: Arena) -> Arc<TreeCell> {
+        Arc::new_cyclic(|cell| TreeCell {
+            tree: RwLock::new(Tree {
+                arena,
+                documents: HashMap::new(),
+                cell: cell.clone(),
+            }),
+            merged: OnceLock::new(),
+        })
+    }
+
+    /// Follows merges from the tree a handle was made in, to the tree its node is in now
+    pub(crate) fn resolve(cell: &Arc<TreeCell>, id: NodeId) -> (&Arc<TreeCell>, NodeId) {
+        let (mut cell, mut id) = (cell, id);
+
+        while let Some((into, offset)) = cell.merged.get() {
+            cell = into;
+            id = id.shifted(*offset);
+        }
+
+        (cell, id)
+    }
+
+    pub(crate) fn is_merged(&self) -> bool {
+        self.merged.get().is_some()
+    }
+
+    /// Locks this tree for writing, even if it was merged already.
+    /// A panic while the tree was locked leaves it as it was: every check happens before anything is changed
+    pub(crate) fn lock(&self) -> RwLockWriteGuard<'_, Tree> {
+        self.tree.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the tree a node is in for reading
+    pub(crate) fn read(cell: &Arc<TreeCell>, id: NodeId) -> (RwLockReadGuard<'_, Tree>, NodeId) {
+        loop {
+            let (cell, id) = TreeCell::resolve(cell, id);
+            let tree = cell.tree.read().unwrap_or_else(PoisonError::into_inner);
+
+            // Merged between resolving and locking
+            if !cell.is_merged() {
+                return (tree, id);
+            }
+        }
+    }
+
+    /// Locks the tree a node is in for writing, see [read](TreeCell::read)
+    pub(crate) fn write(cell: &Arc<TreeCell>, id: NodeId) -> (RwLockWriteGuard<'_, Tree>, NodeId) {
+        loop {
+            let (cell, id) = TreeCell::resolve(cell, id);
+            let tree = cell.lock();
+
+            if !cell.is_merged() {
+                return (tree, id);
+            }
+        }
+    }
+
+    /// Moves the smaller of two trees into the other one, so their nodes can be linked together.
+    /// Does nothing if either was merged in the meantime: callers resolve their handles again and retry
+    pub(crate) fn merge(a: &Arc<TreeCell>, b: &Arc<TreeCell>) {
+        // Always locked in the same order, so that two merges never wait on each other
+        let (first, second) = match Arc::as_ptr(a) < Arc::as_ptr(b) {
+            true => (a, b),
+            false => (b, a),
+        };
+
+        let first_tree = first.lock();
+        let second_tree = second.lock();
+
+        if first.is_merged() || second.is_merged() {
+            return;
+        }
+
+        let (into, mut into_tree, from, mut from_tree) =
+            match first_tree.arena.len() >= second_tree.arena.len() {
+                true => (first, first_tree, second, second_tree),
+                false => (second, second_tree, first, first_tree),
+            };
+
+        let offset = into_tree.absorb(std::mem::take(&mut *from_tree));
+        from.merged
+            .set((into.clone(), offset))
+            .expect("A tree is only merged once");
+    }
+}
+
+impl Tree {
+    /// A handle to one of the nodes of this tree
+    pub(crate) fn node(&self, id: NodeId) -> HTMLNode {
+        let cell = self
+            .cell
+            .upgrade()
+            .expect("A locked tree is kept alive by its lock");
+
+        HTMLNode::at(&cell, id)
+    }
+
+    /// Moves every node of another tree to the end of this one, documents included.
+    /// Returns the offset their handles are shifted by
+    fn absorb(&mut self, other: Tree) -> usize {
+        let offset = self.arena.absorb(other.arena);
+
+        for (root, index) in other.documents {
+            let index = index
+                .into_iter()
+                .map(|(id, nodes)| (id, nodes.into_iter().map(|x| x.shifted(offset)).collect()))
+                .collect();
+
+            self.documents.insert(root.shifted(offset), index);
+        }
+
+        offset
+    }
+
+    /// Roots a document at an element, indexing the ids under it
+    pub(crate) fn add_document(&mut self, root: NodeId) {
+        self.documents.insert(root, IdIndex::new());
+        self.index(root, true, true);
+    }
+
+    /// Forgets the document rooted at an element
+    pub(crate) fn remove_document(&mut self, root: NodeId) {
+        self.documents.remove(&root);
+    }
+
+    /// The id index of the document rooted at an element, if there is one
+    pub(crate) fn document_index(&self, root: NodeId) -> Option<&IdIndex> {
+        self.documents.get(&root)
+    }
+
+    /// Inserts a node among another one's children, like [Arena::insert_before], keeping the id indexes up to date
+    pub(crate) fn insert(&mut self, parent: NodeId, child: NodeId, next: Option<NodeId>) {
+        assert!(
+            !self.arena.is_inclusive_ancestor(child, parent),
+            "A node cannot be inserted into itself or into one of its descendants"
+        );
+
+        self.detach(child);
+
+        // A document rooted at the child loses its elements to the one it joins
+        self.index(child, true, false);
+        self.arena.insert_before(parent, child, next);
+        self.index(child, true, true);
+    }
+
+    /// Disconnects a node from its parent, like [Arena::detach], keeping the id indexes up to date
+    pub(crate) fn detach(&mut self, id: NodeId) {
+        if self.arena.get(id).parent().is_none() {
+            return;
+        }
+
+        self.index(id, true, false);
+        self.arena.detach(id);
+        self.index(id, true, true);
+    }
+
+    /// Edits the attributes of an element, keeping the id indexes up to date if its id may change.
+    /// Panics if the node is not an element
+    pub(crate) fn edit_attributes<F, R>(&mut self, id: NodeId, edits_id: bool, f: F) -> R
+    where
+        F: FnOnce(&mut Attributes) -> R,
+    {
+        if edits_id {
+            self.index(id, false, false);
+        }
+
+        let element = self
+            .arena
+            .element_mut(id)
+            .expect("Only elements have attributes");
+        let result = f(&mut element.attributes);
+
+        if edits_id {
+            self.index(id, false, true);
+        }
+
+        result
+    }
+
+    /// Adds an element, along with the ones under it if `subtree` is set,
+    /// to the id index of the document it belongs to, or removes them from it
+    fn index(&mut self, id: NodeId, subtree: bool, add: bool) {
+        let root = self.arena.ancestors(id).last().unwrap_or(id);
+        let index = match self.documents.get_mut(&root) {
+            None => return,
+            Some(index) => index,
+        };
+
+        let descendants = subtree.then(|| self.arena.descendants(id));
+
+        for node in std::iter::once(id).chain(descendants.into_iter().flatten()) {
+            let element_id = match self
+                .arena
+                .element(node)
+                .and_then(|x| x.attribute("id"))
+                .filter(|x| !x.is_empty())
+            {
+                None => continue,
+                Some(element_id) => element_id,
+            };
+
+            if add {
+                index.entry(element_id.to_string()).or_default().push(node);
+            } else if let Some(nodes) = index.get_mut(element_id) {
+                nodes.retain(|x| *x != node);
+
+                if nodes.is_empty() {
+                    index.remove(element_id);
+                }
+            }
+        }
+    }
+}
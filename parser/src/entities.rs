@@ -0,0 +1,173 @@
+use std::borrow::Cow;
+
+mod names;
+
+use names::NAMED_REFERENCES;
+
+/// Length of the longest named character reference, excluding its `;`
+const LONGEST_NAME: usize = 32;
+
+/// Code points that numeric references in the 0x80..=0x9F range are remapped to.
+/// Those references historically pointed at windows-1252 characters, and browsers still honor it.
+/// A `None` entry means the code point is kept as is.
+const WINDOWS_1252: [Option<char>; 32] = [
+    Some('\u{20AC}'),
+    None,
+    Some('\u{201A}'),
+    Some('\u{0192}'),
+    Some('\u{201E}'),
+    Some('\u{2026}'),
+    Some('\u{2020}'),
+    Some('\u{2021}'),
+    Some('\u{02C6}'),
+    Some('\u{2030}'),
+    Some('\u{0160}'),
+    Some('\u{2039}'),
+    Some('\u{0152}'),
+    None,
+    Some('\u{017D}'),
+    None,
+    None,
+    Some('\u{2018}'),
+    Some('\u{2019}'),
+    Some('\u{201C}'),
+    Some('\u{201D}'),
+    Some('\u{2022}'),
+    Some('\u{2013}'),
+    Some('\u{2014}'),
+    Some('\u{02DC}'),
+    Some('\u{2122}'),
+    Some('\u{0161}'),
+    Some('\u{203A}'),
+    Some('\u{0153}'),
+    None,
+    Some('\u{017E}'),
+    Some('\u{0178}'),
+];
+
+/// Decodes the character references (`&amp;`, `&#38;`, `&#x26;`, ...) contained in a piece of HTML.
+///
+/// `in_attribute` must be set when decoding an attribute value: legacy references without a
+/// trailing `;` are then left untouched when followed by `=` or an alphanumeric character,
+/// so that URLs such as `?a=1&copy=2` survive.
+///
+/// Unknown or malformed references are kept verbatim.
+/// The input is borrowed back when it contains no reference at all.
+pub fn decode_character_references(input: &str, in_attribute: bool) -> Cow<'_, str> {
+    if !input.contains('&') {
+        return Cow::Borrowed(input);
+    }
+
+    let mut decoded = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        let reference = &rest[start + 1..];
+
+        let consumed = if reference.starts_with('#') {
+            consume_numeric_reference(reference, &mut decoded)
+        } else {
+            consume_named_reference(reference, in_attribute, &mut decoded)
+        };
+
+        match consumed {
+            Some(length) => rest = &reference[length..],
+            None => {
+                // Not a reference: keep the ampersand as text
+                decoded.push('&');
+                rest = reference;
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+    Cow::Owned(decoded)
+}
+
+/// Decodes a numeric reference (`#38;` or `#x26;`, the leading `&` already stripped) into `output`.
+/// Returns the number of bytes consumed, or None if this is not a numeric reference.
+fn consume_numeric_reference(reference: &str, output: &mut String) -> Option<usize> {
+    let (radix, digits_start) = match reference.as_bytes().get(1) {
+        Some(b'x' | b'X') => (16, 2),
+        _ => (10, 1),
+    };
+
+    let digits = reference[digits_start..]
+        .bytes()
+        .take_while(|b| (*b as char).is_digit(radix))
+        .count();
+
+    if digits == 0 {
+        return None;
+    }
+
+    // Saturate instead of overflowing: anything above U+10FFFF is replaced anyway
+    let value =
+        reference[digits_start..digits_start + digits]
+            .chars()
+            .fold(0u32, |value, digit| {
+                value
+                    .saturating_mul(radix)
+                    .saturating_add(digit.to_digit(radix).unwrap())
+            });
+
+    output.push(match value {
+        0 | 0xD800..=0xDFFF | 0x110000.. => char::REPLACEMENT_CHARACTER,
+        0x80..=0x9F => {
+            WINDOWS_1252[(value - 0x80) as usize].unwrap_or_else(|| char::from_u32(value).unwrap())
+        }
+        _ => char::from_u32(value).unwrap(),
+    });
+
+    let mut consumed = digits_start + digits;
+    if reference[consumed..].starts_with(';') {
+        consumed += 1;
+    }
+
+    Some(consumed)
+}
+
+/// Decodes a named reference (`amp;`, the leading `&` already stripped) into `output`,
+/// using the longest name that matches.
+/// Returns the number of bytes consumed, or None if no reference should be decoded.
+fn consume_named_reference(
+    reference: &str,
+    in_attribute: bool,
+    output: &mut String,
+) -> Option<usize> {
+    let name_length = reference
+        .bytes()
+        .take(LONGEST_NAME)
+        .take_while(u8::is_ascii_alphanumeric)
+        .count();
+
+    if name_length == 0 {
+        return None;
+    }
+
+    let terminated = reference[name_length..].starts_with(';');
+    let candidates = (1..=name_length).rev().map(|length| &reference[..length]);
+
+    let (name, replacement) = terminated
+        .then(|| &reference[..=name_length])
+        .into_iter()
+        .chain(candidates)
+        .find_map(|name| {
+            NAMED_REFERENCES
+                .binary_search_by_key(&name, |&(key, _)| key)
+                .ok()
+                .map(|index| (name, NAMED_REFERENCES[index].1))
+        })?;
+
+    if in_attribute && !name.ends_with(';') {
+        if let Some(next) = reference[name.len()..].chars().next() {
+            if next == '=' || next.is_ascii_alphanumeric() {
+                return None;
+            }
+        }
+    }
+
+    output.push_str(replacement);
+    Some(name.len())
+}
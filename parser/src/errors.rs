@@ -1,6 +1,15 @@
+use std::fmt::{Display, Formatter};
+
 #[derive(Debug, Clone)]
 pub struct ParserError {
+    /// Byte offset of the error in the source
     pub char: usize,
+    /// Line of the error, starting at 1
+    pub line: usize,
+    /// Column of the error, in characters, starting at 1
+    pub column: usize,
+    /// The source line the error occurred on, without its line ending
+    pub source_line: String,
     pub error_type: ParserErrorType,
 }
 
@@ -11,5 +20,96 @@ pub enum ParserErrorType {
     InvalidName,
     UnexpectedEOF,
     UnmatchedClosingTag,
+    UnclosedTag { name: String },
     UnexpectedCharacter { expected: Vec<char> },
+    UnknownPseudoClass { name: String },
+    DuplicateAttribute { name: String },
+}
+
+impl ParserError {
+    /// Creates an error at a byte offset of the source.
+    /// Its line and column are only known once it has been [located](ParserError::locate)
+    pub(crate) fn new(char: usize, error_type: ParserErrorType) -> ParserError {
+        ParserError {
+            char,
+            line: 0,
+            column: 0,
+            source_line: String::new(),
+            error_type,
+        }
+    }
+
+    /// Creates an [UnexpectedEOF](ParserErrorType::UnexpectedEOF) error.
+    /// Its offset is moved to the end of the source when it is [located](ParserError::locate)
+    pub(crate) fn eof() -> ParserError {
+        ParserError::new(usize::MAX, ParserErrorType::UnexpectedEOF)
+    }
+
+    /// Computes the line and column of this error in the source it was raised from
+    pub(crate) fn locate(mut self, source: &str) -> ParserError {
+        self.char = self.char.min(source.len());
+
+        while !source.is_char_boundary(self.char) {
+            self.char -= 1;
+        }
+
+        let line_start = source[..self.char].rfind('\n').map_or(0, |x| x + 1);
+        let line_end = source[self.char..]
+            .find('\n')
+            .map_or(source.len(), |x| self.char + x);
+
+        self.line = source[..line_start].matches('\n').count() + 1;
+        self.column = source[line_start..self.char].chars().count() + 1;
+        self.source_line = source[line_start..line_end]
+            .trim_end_matches('\r')
+            .to_string();
+        self
+    }
+}
+
+impl Display for ParserErrorType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParserErrorType::InvalidAttribute => write!(f, "invalid attribute"),
+            ParserErrorType::InvalidValue => write!(f, "invalid attribute value"),
+            ParserErrorType::InvalidName => write!(f, "invalid tag name"),
+            ParserErrorType::UnexpectedEOF => write!(f, "unexpected end of file"),
+            ParserErrorType::UnmatchedClosingTag => write!(f, "unmatched closing tag"),
+            ParserErrorType::UnclosedTag { name } => write!(f, "unclosed <{}> tag", name),
+            ParserErrorType::UnknownPseudoClass { name } => {
+                write!(f, "unknown pseudo-class :{}", name)
+            }
+            ParserErrorType::DuplicateAttribute { name } => {
+                write!(f, "duplicate attribute {}", name)
+            }
+            ParserErrorType::UnexpectedCharacter { expected } => {
+                write!(f, "unexpected character, expected ")?;
+
+                let expected: Vec<String> = expected.iter().map(|x| format!("'{}'", x)).collect();
+                write!(f, "{}", expected.join(" or "))
+            }
+        }
+    }
+}
+
+/// Prints the error, followed by the offending source line and a caret under the error
+impl Display for ParserError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} at line {}, column {}",
+            self.error_type, self.line, self.column
+        )?;
+
+        let gutter = self.line.to_string();
+        let padding: String = self
+            .source_line
+            .chars()
+            .take(self.column.saturating_sub(1))
+            .map(|x| if x == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        writeln!(f, "{} | {}", gutter, self.source_line)?;
+        write!(f, "{} | {}^", " ".repeat(gutter.len()), padding)
+    }
 }
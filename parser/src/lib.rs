@@ -1,5 +1,6 @@
+pub mod entities;
 pub mod errors;
 pub mod parse;
 
 #[cfg(feature = "query_parse")]
-mod queries;
+pub mod queries;
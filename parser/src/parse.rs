@@ -1,7 +1,13 @@
+use crate::entities::decode_character_references;
 use crate::errors::ParserErrorType::*;
 use crate::errors::{ParserError, ParserErrorType};
+use std::borrow::Cow;
+use std::fmt::Debug;
 use std::iter::Peekable;
 
+use dom::borrowed::{BorrowedEnum, BorrowedNode};
+use dom::doctype::Doctype;
+use dom::document::HTMLDocument;
 use dom::html_elements::*;
 
 //What characters can start a tag name
@@ -32,12 +38,12 @@ macro_rules! arg_name_pattern {
 
 #[macro_export]
 macro_rules! val_name_starter_pattern {
-    () => { 'A' ..= 'Z' | 'a' ..= 'z' | '#' | '/' | '0' ..= '9' };
+    () => { 'A' ..= 'Z' | 'a' ..= 'z' | '#' | '/' | '0' ..= '9' | '&' };
 }
 
 #[macro_export]
 macro_rules! val_name_pattern {
-    () => { 'A' ..= 'Z' | 'a' ..= 'z' | '0' ..= '9' | '-' | '_' | ':' | '.' | ',' | '/' | '!' };
+    () => { 'A' ..= 'Z' | 'a' ..= 'z' | '0' ..= '9' | '-' | '_' | ':' | '.' | ',' | '/' | '!' | '&' | '#' | ';' };
 }
 
 #[macro_export]
@@ -54,6 +60,7 @@ macro_rules! equal_sign {
     };
 }
 
+/// Starts a character reference such as `&amp;` or `&#x27;`
 #[macro_export]
 macro_rules! html_encoded_start {
     () => {
@@ -102,11 +109,298 @@ macro_rules! special_indicator {
 
 /// Some html tags can only have text as a child, meaning we can just skip characters until we find
 /// the matching tag end.
-pub const __QUICKPARSE: &[&str] = &["script", "style"];
+pub const __QUICKPARSE: &[&str] = dom::serialize::__RAW_TEXT;
+
+/// Elements whose end tag can be omitted: they are implicitly closed when their parent is,
+/// or at the end of the document
+pub const __OPTIONAL_END: &[&str] = &[
+    "caption", "colgroup", "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc",
+    "tbody", "td", "tfoot", "th", "thead", "tr", "html", "head", "body",
+];
+
+/// Opening any of these elements closes a `<p>` element that is still open
+pub const __CLOSES_P: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "center",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "listing",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "plaintext",
+    "pre",
+    "search",
+    "section",
+    "summary",
+    "table",
+    "ul",
+    "xmp",
+    "li",
+    "dd",
+    "dt",
+];
+
+/// Elements the HTML5 tree builder treats specially.
+/// A list item being opened does not look for a previous one past these elements
+const SPECIAL: &[&str] = &[
+    "address",
+    "applet",
+    "area",
+    "article",
+    "aside",
+    "base",
+    "basefont",
+    "bgsound",
+    "blockquote",
+    "body",
+    "br",
+    "button",
+    "caption",
+    "center",
+    "col",
+    "colgroup",
+    "dd",
+    "details",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "embed",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "frame",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hgroup",
+    "hr",
+    "html",
+    "iframe",
+    "img",
+    "input",
+    "keygen",
+    "li",
+    "link",
+    "listing",
+    "main",
+    "marquee",
+    "menu",
+    "meta",
+    "nav",
+    "noembed",
+    "noframes",
+    "noscript",
+    "object",
+    "ol",
+    "p",
+    "param",
+    "plaintext",
+    "pre",
+    "script",
+    "search",
+    "section",
+    "select",
+    "source",
+    "style",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "template",
+    "textarea",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "track",
+    "ul",
+    "wbr",
+    "xmp",
+];
+
+/// Elements that bound the search for an open element to close
+const SCOPE: &[&str] = &[
+    "applet", "caption", "html", "table", "td", "th", "marquee", "object", "template",
+];
+
+/// Elements that bound the search for an open table part to close
+const TABLE_SCOPE: &[&str] = &["html", "table", "template"];
+
+const HEADINGS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6"];
+
+/// Camel-cased SVG attributes, which keep their case inside `<svg>` elements instead of being lowercased
+const SVG_ATTRIBUTES: &[&str] = &[
+    "attributeName",
+    "attributeType",
+    "baseFrequency",
+    "baseProfile",
+    "calcMode",
+    "clipPathUnits",
+    "diffuseConstant",
+    "edgeMode",
+    "filterUnits",
+    "glyphRef",
+    "gradientTransform",
+    "gradientUnits",
+    "kernelMatrix",
+    "kernelUnitLength",
+    "keyPoints",
+    "keySplines",
+    "keyTimes",
+    "lengthAdjust",
+    "limitingConeAngle",
+    "markerHeight",
+    "markerUnits",
+    "markerWidth",
+    "maskContentUnits",
+    "maskUnits",
+    "numOctaves",
+    "pathLength",
+    "patternContentUnits",
+    "patternTransform",
+    "patternUnits",
+    "pointsAtX",
+    "pointsAtY",
+    "pointsAtZ",
+    "preserveAlpha",
+    "preserveAspectRatio",
+    "primitiveUnits",
+    "refX",
+    "refY",
+    "repeatCount",
+    "repeatDur",
+    "requiredExtensions",
+    "requiredFeatures",
+    "specularConstant",
+    "specularExponent",
+    "spreadMethod",
+    "startOffset",
+    "stdDeviation",
+    "stitchTiles",
+    "surfaceScale",
+    "systemLanguage",
+    "tableValues",
+    "targetX",
+    "targetY",
+    "textLength",
+    "viewBox",
+    "viewTarget",
+    "xChannelSelector",
+    "yChannelSelector",
+    "zoomAndPan",
+];
 
 type Letter = char;
 type CharIter = (usize, Letter);
 
+/// An element still open while parsing, along with the nodes met before it in its parent.
+/// Its children are only attached once it is closed
+type Layer<'a, T> = (Vec<T>, BorrowedNode<'a>);
+
+/// A node of the tree the parser builds, borrowing from the source like [BorrowedEnum].
+/// Elements are built once closed, out of their tag and their children, so the tree is only built once.
+/// Owned trees are copied from it in one go, into a single arena
+trait TreeNode<'a>: Sized + Debug {
+    fn text(text: Cow<'a, str>) -> Self;
+    fn comment(comment: &'a str) -> Self;
+    fn doctype(doctype: Doctype) -> Self;
+    fn element(tag: BorrowedNode<'a>, children: Vec<Self>) -> Self;
+}
+
+impl<'a> TreeNode<'a> for BorrowedEnum<'a> {
+    fn text(text: Cow<'a, str>) -> Self {
+        BorrowedEnum::Text(text)
+    }
+
+    fn comment(comment: &'a str) -> Self {
+        BorrowedEnum::Comment(comment)
+    }
+
+    fn doctype(doctype: Doctype) -> Self {
+        BorrowedEnum::Doctype(doctype)
+    }
+
+    fn element(mut tag: BorrowedNode<'a>, children: Vec<Self>) -> Self {
+        tag.children = children;
+        BorrowedEnum::Node(tag)
+    }
+}
+
+/// The value of an attribute, if it has one, only owned when character references had to be decoded
+type AttributeValue<'a> = Option<Cow<'a, str>>;
+
+/// Lowercases a tag or attribute name, only allocating if it has uppercase letters
+fn lowercase(name: &str) -> Cow<'_, str> {
+    if name.bytes().any(|x| x.is_ascii_uppercase()) {
+        Cow::Owned(name.to_ascii_lowercase())
+    } else {
+        Cow::Borrowed(name)
+    }
+}
+
+/// Adds an attribute to a tag being parsed, lowercasing its name unless it is a SVG attribute inside `<svg>`.
+/// As in browsers, the first occurrence of a duplicate attribute wins, and the others are reported
+fn add_attribute<'a>(
+    node: &mut BorrowedNode<'a>,
+    (name, value): (&'a str, AttributeValue<'a>),
+    in_svg: bool,
+    offset: usize,
+    diagnostics: &mut Diagnostics,
+) {
+    let svg_name = SVG_ATTRIBUTES
+        .iter()
+        .find(|x| in_svg && x.eq_ignore_ascii_case(name));
+
+    let name = match svg_name {
+        Some(svg_name) => Cow::Borrowed(*svg_name),
+        None => lowercase(name),
+    };
+
+    if node.get_attribute(&name).is_some() {
+        diagnostics.warn(ParserError::new(
+            offset,
+            DuplicateAttribute {
+                name: name.into_owned(),
+            },
+        ));
+    } else {
+        node.add_attribute(name, value);
+    }
+}
+
 pub(crate) fn consume_whitespaces<I>(mut characters: Peekable<I>) -> Peekable<I>
 where
     I: Iterator<Item = CharIter> + Clone,
@@ -158,29 +452,16 @@ where
             from = *i;
             characters.next();
         }
-        Some((i, _)) => {
-            return Err(ParserError {
-                char: *i,
-                error_type: InvalidName,
-            })
-        }
-        None => {
-            return Err(ParserError {
-                char: 0,
-                error_type: UnexpectedEOF,
-            })
-        }
+        Some((i, _)) => return Err(ParserError::new(*i, InvalidName)),
+        None => return Err(ParserError::eof()),
     }
 
-    let mut to: usize = from + 1;
+    let to: usize;
 
     loop {
         match characters.peek() {
             None => {
-                return Err(ParserError {
-                    char: 0,
-                    error_type: UnexpectedEOF,
-                });
+                return Err(ParserError::eof());
             }
             Some((_, tag_name_pattern!())) => {
                 characters.next();
@@ -192,7 +473,7 @@ where
         }
     }
 
-    return Ok((characters, from, to));
+    Ok((characters, from, to))
 }
 
 /// Given a html-formatted string starting with a tag attribute, extracts the attribute and returns a slice.
@@ -205,9 +486,8 @@ where
 {
     let mut quoted: Option<Letter> = None;
     let from: usize;
-    let to: usize;
 
-    match characters.nth(0) {
+    match characters.next() {
         Some((i, quotation_mark @ quotes_symbol!())) if quoted.is_none() => {
             quoted = Some(quotation_mark);
             from = i + 1;
@@ -215,18 +495,8 @@ where
         Some((i, arg_name_starter_pattern!())) => {
             from = i;
         }
-        Some((i, _)) => {
-            return Err(ParserError {
-                char: i,
-                error_type: InvalidAttribute,
-            })
-        }
-        None => {
-            return Err(ParserError {
-                char: 0,
-                error_type: UnexpectedEOF,
-            })
-        }
+        Some((i, _)) => return Err(ParserError::new(i, InvalidAttribute)),
+        None => return Err(ParserError::eof()),
     }
 
     let p: Option<usize> = match quoted {
@@ -235,14 +505,11 @@ where
     };
 
     if p.is_none() {
-        return Err(ParserError {
-            char: 0,
-            error_type: UnexpectedEOF,
-        });
+        return Err(ParserError::eof());
     }
 
     let (upper_bound, _) = characters.peek().unwrap();
-    to = *upper_bound;
+    let to: usize = *upper_bound;
 
     if quoted.is_some() {
         characters.next();
@@ -270,18 +537,8 @@ where
         Some((i, val_name_starter_pattern!())) => {
             from = i;
         }
-        Some((i, _)) => {
-            return Err(ParserError {
-                char: i,
-                error_type: InvalidValue,
-            })
-        }
-        None => {
-            return Err(ParserError {
-                char: 0,
-                error_type: UnexpectedEOF,
-            })
-        }
+        Some((i, _)) => return Err(ParserError::new(i, InvalidValue)),
+        None => return Err(ParserError::eof()),
     }
 
     let p: Option<usize> = match quoted {
@@ -290,10 +547,7 @@ where
     };
 
     if p.is_none() {
-        return Err(ParserError {
-            char: 0,
-            error_type: UnexpectedEOF,
-        });
+        return Err(ParserError::eof());
     }
 
     let (upper_bound, _) = characters.peek().unwrap();
@@ -308,18 +562,18 @@ where
 
 /// Given a html-formatted string starting with a tag attribute, extracts the attribute, its value, and returns them.
 /// Works with a peekable iterator of (index, letter) instead of a raw string
-fn consume_attr_value<I>(
+fn consume_attr_value<'a, I>(
     mut characters: Peekable<I>,
-    document: &str,
-) -> Result<(Peekable<I>, String, Option<String>), ParserError>
+    document: &'a str,
+) -> Result<(Peekable<I>, &'a str, AttributeValue<'a>), ParserError>
 where
     I: Iterator<Item = CharIter> + Clone,
 {
     let (char, (from, to)) = consume_attribute(characters)?;
     characters = char;
-    let attribute = String::from(&document[from..to]);
+    let attribute = &document[from..to];
 
-    let value: Option<String>;
+    let value: AttributeValue;
 
     match characters.peek() {
         Some((_, equal_sign!())) => {
@@ -327,40 +581,181 @@ where
             let (char, (from, to)) = consume_value(characters)?;
             characters = char;
 
-            value = Some(String::from(&document[from..to]));
+            value = Some(decode_character_references(&document[from..to], true));
         }
         Some(_) => value = None,
-        None => {
-            return Err(ParserError {
-                char: 0,
-                error_type: UnexpectedEOF,
-            })
-        }
+        None => return Err(ParserError::eof()),
     };
 
     Ok((characters, attribute, value))
 }
 
+/// Given a html-formatted string following a `<!DOCTYPE` opener, extracts the doctype up to its closing `>`.
+/// Works with a peekable iterator of (index, letter) instead of a raw string.
+/// A malformed doctype is still returned, with its force-quirks flag set, along with the error
+fn consume_doctype<I>(
+    mut characters: Peekable<I>,
+    document: &str,
+) -> (Peekable<I>, Doctype, Option<ParserError>)
+where
+    I: Iterator<Item = CharIter> + Clone,
+{
+    let start = characters.peek().map_or(document.len(), |(i, _)| *i);
+    let (doctype, length, error) = parse_doctype(&document[start..], start);
+
+    peek_pos_til(&mut characters, |(i, _)| *i >= start + length);
+    (characters, doctype, error)
+}
+
+/// Parses the contents of a doctype (` html PUBLIC "..." "...">`), up to and including its closing `>`.
+/// `offset` is the position of `source` in the document.
+/// Returns the doctype, the number of bytes it spans, and the error that made it malformed, if any
+#[allow(clippy::manual_pattern_char_comparison)] // Characters are matched with the grammar macros
+fn parse_doctype(source: &str, offset: usize) -> (Doctype, usize, Option<ParserError>) {
+    let mut doctype = Doctype::default();
+    let skip_whitespaces = |position: usize| {
+        source[position..]
+            .find(|c| !matches!(c, whitespace!()))
+            .map_or(source.len(), |x| position + x)
+    };
+
+    // Skips the rest of a malformed doctype, up to its end.
+    // Browsers render documents in quirks mode when a doctype is cut by the end of the file
+    let skip_malformed =
+        |mut doctype: Doctype, position: usize, error_type: ParserErrorType| match source
+            [position..]
+            .find(|c| matches!(c, tag_closer!()))
+        {
+            None => {
+                doctype.force_quirks = true;
+                (doctype, source.len(), Some(ParserError::eof()))
+            }
+            Some(x) => (
+                doctype,
+                position + x + 1,
+                Some(ParserError::new(offset + position, error_type)),
+            ),
+        };
+
+    // Reads a quoted identifier, returning it along with the position following its closing quote
+    let quoted = |position: usize| -> Result<(String, usize), ParserErrorType> {
+        let quote_sign = match source[position..].chars().next() {
+            Some(quote_sign @ quotes_symbol!()) => quote_sign,
+            None => return Err(UnexpectedEOF),
+            _ => {
+                return Err(UnexpectedCharacter {
+                    expected: vec!['"', '\''],
+                })
+            }
+        };
+        let contents = &source[position + 1..];
+
+        match contents.find(|c| c == quote_sign || matches!(c, tag_closer!())) {
+            Some(x) if contents[x..].starts_with(quote_sign) => {
+                Ok((String::from(&contents[..x]), position + 1 + x + 1))
+            }
+            Some(_) => Err(UnexpectedCharacter {
+                expected: vec![quote_sign],
+            }),
+            None => Err(UnexpectedEOF),
+        }
+    };
+
+    // Name
+    let mut position = skip_whitespaces(0);
+    let name_length = source[position..]
+        .find(|c| matches!(c, whitespace!() | tag_closer!()))
+        .unwrap_or(source.len() - position);
+
+    if name_length == 0 {
+        doctype.force_quirks = true;
+        return skip_malformed(doctype, position, InvalidName);
+    }
+    doctype.name = source[position..position + name_length].to_ascii_lowercase();
+    position = skip_whitespaces(position + name_length);
+
+    if source[position..].starts_with(|c| matches!(c, tag_closer!())) {
+        return (doctype, position + 1, None);
+    }
+
+    // Identifiers
+    let keyword = source[position..].get(..6).map(str::to_ascii_lowercase);
+    let public = match keyword.as_deref() {
+        Some("public") => true,
+        Some("system") => false,
+        _ => {
+            doctype.force_quirks = true;
+            return skip_malformed(
+                doctype,
+                position,
+                UnexpectedCharacter {
+                    expected: vec!['>'],
+                },
+            );
+        }
+    };
+    position = skip_whitespaces(position + 6);
+
+    match quoted(position) {
+        Err(error_type) => {
+            doctype.force_quirks = true;
+            return skip_malformed(doctype, position, error_type);
+        }
+        Ok((id, next)) if public => {
+            doctype.public_id = Some(id);
+            position = skip_whitespaces(next);
+        }
+        Ok((id, next)) => {
+            doctype.system_id = Some(id);
+            position = skip_whitespaces(next);
+        }
+    }
+
+    // A system identifier can follow the public one
+    if public && source[position..].starts_with(|c| matches!(c, quotes_symbol!())) {
+        match quoted(position) {
+            Err(error_type) => {
+                doctype.force_quirks = true;
+                return skip_malformed(doctype, position, error_type);
+            }
+            Ok((id, next)) => {
+                doctype.system_id = Some(id);
+                position = skip_whitespaces(next);
+            }
+        }
+    }
+
+    if source[position..].starts_with(|c| matches!(c, tag_closer!())) {
+        return (doctype, position + 1, None);
+    }
+
+    // Anything left after the identifiers is ignored
+    skip_malformed(
+        doctype,
+        position,
+        UnexpectedCharacter {
+            expected: vec!['>'],
+        },
+    )
+}
+
 /// Closes a HTML Tag, putting all the elements in last_layer as children of that Tag.
 /// Takes into account self-closed elements
 ///
 /// Returns Ok(layer the closed tags belong to) if a tag was closed
 /// Returns Err(layer that was supposed to be the tag's children) if no viable parent is found the layer_stack
-fn fold(
-    layer_stack: &mut Vec<(Vec<HTMLEnum>, HTMLNodeRef)>,
-    mut last_layer: Vec<HTMLEnum>,
-) -> Result<Vec<HTMLEnum>, Vec<HTMLEnum>> {
+fn fold<'a, T: TreeNode<'a>>(
+    layer_stack: &mut Vec<Layer<'a, T>>,
+    mut last_layer: Vec<T>,
+) -> Result<Vec<T>, Vec<T>> {
     match layer_stack.pop() {
         None => Err(last_layer),
         Some((mut contents, tag)) => {
-            if !__SELF_CLOSED.contains(&&*tag.borrow().name().to_lowercase()) {
-                let mut tagborrow = tag.borrow_mut();
-                tagborrow.add_children(last_layer);
-                drop(tagborrow);
-                contents.push(HTMLEnum::Node(tag));
+            if !tag.self_closing() {
+                contents.push(T::element(tag, last_layer));
                 Ok(contents)
             } else {
-                contents.push(HTMLEnum::Node(tag));
+                contents.push(T::element(tag, vec![]));
                 contents.append(&mut last_layer);
                 Ok(contents)
             }
@@ -368,15 +763,123 @@ fn fold(
     }
 }
 
+/// Folds the layer stack until the element at `depth` is closed
+fn fold_to<'a, T: TreeNode<'a>>(
+    layer_stack: &mut Vec<Layer<'a, T>>,
+    mut last_layer: Vec<T>,
+    depth: usize,
+) -> Vec<T> {
+    while layer_stack.len() > depth {
+        last_layer = fold(layer_stack, last_layer).unwrap();
+    }
+    last_layer
+}
+
+/// Finds the depth of the closest open element named in `targets`,
+/// without looking past an element named in `boundaries`
+fn find_in_scope<T>(
+    layer_stack: &[Layer<T>],
+    targets: &[&str],
+    boundaries: &[&str],
+) -> Option<usize> {
+    for (depth, (_, node)) in layer_stack.iter().enumerate().rev() {
+        if targets.contains(&node.name()) {
+            return Some(depth);
+        }
+        if boundaries.contains(&node.name()) {
+            return None;
+        }
+    }
+
+    None
+}
+
+/// Closes the elements that are implicitly ended by opening a `name` tag,
+/// following the HTML5 tree construction rules.
+/// For example, `<p>a<p>b` gives two sibling paragraphs, and so does `<li>a<li>b` for list items
+fn close_implied<'a, T: TreeNode<'a>>(
+    layer_stack: &mut Vec<Layer<'a, T>>,
+    mut last_layer: Vec<T>,
+    name: &str,
+) -> Vec<T> {
+    let top_is = |layer_stack: &[Layer<T>], names: &[&str]| {
+        layer_stack
+            .last()
+            .is_some_and(|(_, node)| names.contains(&node.name()))
+    };
+
+    if __CLOSES_P.contains(&name) {
+        let button_scope = [SCOPE, &["button"]].concat();
+
+        if let Some(depth) = find_in_scope(layer_stack, &["p"], &button_scope) {
+            last_layer = fold_to(layer_stack, last_layer, depth);
+        }
+    }
+
+    let list_items: &[&str] = match name {
+        "li" => &["li"],
+        "dd" | "dt" => &["dd", "dt"],
+        _ => &[],
+    };
+
+    if !list_items.is_empty() {
+        // Look for a previous item, unless it is in another special element (like a nested list)
+        let boundaries: Vec<&str> = SPECIAL
+            .iter()
+            .filter(|x| !matches!(**x, "address" | "div" | "p") && !list_items.contains(x))
+            .copied()
+            .collect();
+
+        if let Some(depth) = find_in_scope(layer_stack, list_items, &boundaries) {
+            last_layer = fold_to(layer_stack, last_layer, depth);
+        }
+    }
+
+    let table_parts: &[&str] = match name {
+        "tbody" | "thead" | "tfoot" => &["tbody", "thead", "tfoot"],
+        "tr" => &["tr"],
+        "td" | "th" => &["td", "th"],
+        _ => &[],
+    };
+
+    if !table_parts.is_empty() {
+        if let Some(depth) = find_in_scope(layer_stack, table_parts, TABLE_SCOPE) {
+            last_layer = fold_to(layer_stack, last_layer, depth);
+        }
+    }
+
+    match name {
+        "option" | "optgroup" => {
+            if top_is(layer_stack, &["option"]) {
+                last_layer = fold(layer_stack, last_layer).unwrap();
+            }
+            if name == "optgroup" && top_is(layer_stack, &["optgroup"]) {
+                last_layer = fold(layer_stack, last_layer).unwrap();
+            }
+        }
+        "button" => {
+            if let Some(depth) = find_in_scope(layer_stack, &["button"], SCOPE) {
+                last_layer = fold_to(layer_stack, last_layer, depth);
+            }
+        }
+        _ if HEADINGS.contains(&name) && top_is(layer_stack, HEADINGS) => {
+            last_layer = fold(layer_stack, last_layer).unwrap();
+        }
+        _ => {}
+    }
+
+    last_layer
+}
+
 /*
 * Forcibly folds the entire HTML layer stack
 * layer_stack : list of opened layers, each layer being (contents, opened tag)
 * last_layer : list of elements in the last layer
 */
-fn fold_all(
-    layer_stack: &mut Vec<(Vec<HTMLEnum>, HTMLNodeRef)>,
-    mut last_layer: Vec<HTMLEnum>,
-) -> Vec<HTMLEnum> {
+fn fold_all<'a, T: TreeNode<'a>>(
+    layer_stack: &mut Vec<Layer<'a, T>>,
+    mut last_layer: Vec<T>,
+) -> Vec<T> {
     loop {
         match fold(layer_stack, last_layer) {
             Err(x) => {
@@ -389,6 +892,11 @@ fn fold_all(
     }
 }
 
+/// Creates a text token from a slice of the document, decoding its character references
+fn text_token<'a, T: TreeNode<'a>>(text: &'a str) -> T {
+    T::text(decode_character_references(text, false))
+}
+
 fn find_word<I>(source: &mut Peekable<I>, word: &str) -> Option<usize>
 where
     I: Iterator<Item = CharIter> + Clone,
@@ -409,6 +917,131 @@ where
     None // Word not found
 }
 
+/// Turns byte offsets into lines and columns, for positions met in increasing order.
+/// Each call only looks at the source since the previous position
+struct Locator {
+    position: SourcePosition,
+}
+
+impl Locator {
+    fn new() -> Locator {
+        Locator {
+            position: SourcePosition {
+                offset: 0,
+                line: 1,
+                column: 1,
+            },
+        }
+    }
+
+    /// The position of a byte offset of the source, which must not be before the previous one
+    fn locate(&mut self, source: &str, offset: usize) -> SourcePosition {
+        for x in source[self.position.offset..offset].chars() {
+            if x == '\n' {
+                self.position.line += 1;
+                self.position.column = 1;
+            } else {
+                self.position.column += 1;
+            }
+        }
+
+        self.position.offset = offset;
+        self.position
+    }
+}
+
+/// Collects the errors met while parsing a document.
+/// In lenient mode, recoverable errors are stored and parsing goes on.
+/// In strict mode, the first error stops the parser.
+struct Diagnostics {
+    lenient: bool,
+    errors: Vec<ParserError>,
+}
+
+impl Diagnostics {
+    /// Records a recoverable error.
+    /// In strict mode, the error is given back so it can be propagated with `?`
+    fn report(&mut self, error: ParserError) -> Result<(), ParserError> {
+        if self.lenient {
+            self.errors.push(error);
+            Ok(())
+        } else {
+            Err(error)
+        }
+    }
+
+    /// Records a problem the strict parser tolerates, such as elements left open at the end of the document.
+    /// It is only kept in lenient mode
+    fn warn(&mut self, error: ParserError) {
+        if self.lenient {
+            self.errors.push(error);
+        }
+    }
+}
+
+/// Folds the entire layer stack once the end of the document is reached,
+/// reporting every element that was never closed
+fn close_document<'a, T: TreeNode<'a>>(
+    layer_stack: &mut Vec<Layer<'a, T>>,
+    last_layer: Vec<T>,
+    diagnostics: &mut Diagnostics,
+    end: usize,
+) -> Vec<T> {
+    for (_, node) in layer_stack.iter() {
+        if !node.self_closing() && !__OPTIONAL_END.contains(&node.name()) {
+            diagnostics.warn(ParserError::new(
+                end,
+                UnclosedTag {
+                    name: node.name().to_string(),
+                },
+            ));
+        }
+    }
+
+    fold_all(layer_stack, last_layer)
+}
+
+/// Given a html-formatted string starting with a malformed attribute, extracts it as literal text,
+/// the way browsers do: the name runs until whitespace, `=`, `/` or `>`,
+/// and an unquoted value until whitespace or `>`.
+/// Only fails if the end of the document is reached inside a quoted value
+fn consume_raw_attribute<I>(
+    mut characters: Peekable<I>,
+    document: &str,
+) -> Result<(Peekable<I>, &str), ParserError>
+where
+    I: Iterator<Item = CharIter> + Clone,
+{
+    let (from, _) = characters.next().ok_or_else(ParserError::eof)?;
+    peek_pos_til(&mut characters, |(_, x)| {
+        matches!(
+            x,
+            whitespace!() | equal_sign!() | tag_self_closer!() | tag_closer!()
+        )
+    });
+
+    if matches!(characters.peek(), Some((_, equal_sign!()))) {
+        characters.next();
+
+        match characters.peek() {
+            Some(&(_, quote_sign @ quotes_symbol!())) => {
+                characters.next();
+                peek_pos_til(&mut characters, |(_, x)| *x == quote_sign)
+                    .ok_or_else(ParserError::eof)?;
+                characters.next();
+            }
+            _ => {
+                peek_pos_til(&mut characters, |(_, x)| {
+                    matches!(x, whitespace!() | tag_closer!())
+                });
+            }
+        }
+    }
+
+    let to = characters.peek().map_or(document.len(), |(i, _)| *i);
+    Ok((characters, &document[from..to]))
+}
+
 /// Parses a HTML Document
 ///
 /// ### Returns
@@ -418,21 +1051,99 @@ where
 /// Returns an error if anything is incorrect in the document grammar.
 /// For more information, please refer to [ParserError]
 pub fn parse_html(document: &str) -> Result<Vec<HTMLEnum>, ParserError> {
-    // TODO: Make some errors recoverable
+    parse_strict(document).map(|tree: Vec<BorrowedEnum>| BorrowedEnum::to_html_enums(&tree))
+}
+
+/// Parses a HTML Document without copying it: names, attributes, text and comments are slices of `document`,
+/// only owned when they had to be lowercased or had character references decoded.
+/// Fails on the same documents as [parse_html]
+///
+/// ### Returns
+/// A vector containing the top-level elements
+pub fn parse_html_borrowed(document: &str) -> Result<Vec<BorrowedEnum<'_>>, ParserError> {
+    parse_strict(document)
+}
+
+/// Parses a HTML Document into either tree, stopping at the first error
+fn parse_strict<'a, T: TreeNode<'a>>(document: &'a str) -> Result<Vec<T>, ParserError> {
+    let mut diagnostics = Diagnostics {
+        lenient: false,
+        errors: vec![],
+    };
+
+    parse_tokens(document, &mut diagnostics).map_err(|error| error.locate(document))
+}
+
+/// Parses a HTML Document, recovering from errors the way browsers do:
+/// stray closing tags are skipped, elements left open are closed at the end of the document,
+/// and malformed attributes are kept as literal text, in front of their element.
+///
+/// ### Returns
+/// The top-level elements, along with every error met in the document, in order
+pub fn parse_html_lenient(document: &str) -> (Vec<HTMLEnum>, Vec<ParserError>) {
+    let mut diagnostics = Diagnostics {
+        lenient: true,
+        errors: vec![],
+    };
+
+    // Every error is recovered from in lenient mode, so this only guards against a parser bug
+    let tokens = match parse_tokens::<BorrowedEnum>(document, &mut diagnostics) {
+        Ok(tokens) => BorrowedEnum::to_html_enums(&tokens),
+        Err(error) => {
+            diagnostics.errors.push(error);
+            vec![]
+        }
+    };
+    let errors = diagnostics
+        .errors
+        .into_iter()
+        .map(|error| error.locate(document))
+        .collect();
+
+    (tokens, errors)
+}
+
+/// Builds a [HTMLDocument] straight from its source.
+/// Lives here, as the dom crate cannot depend on the parser
+pub trait ParseDocument: Sized {
+    /// Parses a HTML Document, sorting its elements into the head and the body.
+    /// Fails on the same documents as [parse_html]
+    fn parse(document: &str) -> Result<Self, ParserError>;
+}
+
+impl ParseDocument for HTMLDocument {
+    fn parse(document: &str) -> Result<HTMLDocument, ParserError> {
+        parse_html(document).map(HTMLDocument::from_tokens)
+    }
+}
+
+/// Parses a HTML Document, leaving the errors' line and column unset.
+/// Never fails in lenient mode
+fn parse_tokens<'a, T: TreeNode<'a>>(
+    document: &'a str,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<T>, ParserError> {
     let mut source = document.char_indices().peekable();
     let mut layer_stack = vec![];
     let mut last_layer = vec![];
-    let mut doctype_attributes: Vec<(String, Option<String>)> = vec![];
+    let mut locator = Locator::new();
 
-    let is_self_closable = |name: &str| __SELF_CLOSED.contains(&&*name.to_lowercase());
     let mut text_used = true;
     let mut text_start: usize = 0; // dummy default value. Is instantly overwritten below
+    let end = document.len();
 
     loop {
         // Update text start position if needed
         if text_used {
             text_start = match source.peek() {
-                None => return Ok(fold_all(&mut layer_stack, last_layer)),
+                None => {
+                    return Ok(close_document(
+                        &mut layer_stack,
+                        last_layer,
+                        diagnostics,
+                        end,
+                    ))
+                }
                 Some((i, _)) => *i,
             };
             text_used = true;
@@ -446,8 +1157,13 @@ pub fn parse_html(document: &str) -> Result<Vec<HTMLEnum>, ParserError> {
             // If we cannot find a tag starter, of if there is nothing after it, the document ends
             if x.is_none() || source.peek().is_none() {
                 // No tag found - everything left in the document is text
-                last_layer.push(HTMLEnum::Text(String::from(&document[text_start..])));
-                return Ok(fold_all(&mut layer_stack, last_layer));
+                last_layer.push(text_token(&document[text_start..]));
+                return Ok(close_document(
+                    &mut layer_stack,
+                    last_layer,
+                    diagnostics,
+                    end,
+                ));
             }
 
             x.unwrap().0 // position (usize)
@@ -461,63 +1177,108 @@ pub fn parse_html(document: &str) -> Result<Vec<HTMLEnum>, ParserError> {
             tag_self_closer!() => {
                 // Anything left in cache before this tag should be considered text
                 if tag_start != text_start {
-                    last_layer.push(HTMLEnum::Text(String::from(
-                        &document[text_start..tag_start],
-                    )));
+                    last_layer.push(text_token(&document[text_start..tag_start]));
                 }
 
                 source.next(); // Consume slash character
 
                 // Consume tag name
-                let (_source, from, to) = consume_tag_name(source)?;
-                source = _source;
+                let (from, to) = match consume_tag_name(source.clone()) {
+                    Ok((_source, from, to)) => {
+                        source = _source;
+                        (from, to)
+                    }
+                    Err(error) => {
+                        let eof = matches!(error.error_type, UnexpectedEOF);
+                        diagnostics.report(error)?;
+
+                        // Not a closing tag: skip it entirely
+                        if eof || source.find(|(_, c)| matches!(c, tag_closer!())).is_none() {
+                            return Ok(close_document(
+                                &mut layer_stack,
+                                last_layer,
+                                diagnostics,
+                                end,
+                            ));
+                        }
+                        continue;
+                    }
+                };
 
                 // Extract the actual name
-                let closer_name = &document[from..to];
+                let closer_name = lowercase(&document[from..to]);
 
                 // Verify the tag closer ends with '>'
-                {
-                    let n = source.next();
-                    if n.is_none() {
-                        return Err(ParserError {
-                            char: 0,
-                            error_type: UnexpectedEOF,
-                        });
+                source = consume_whitespaces(source);
+                match source.next() {
+                    None => {
+                        diagnostics.report(ParserError::eof())?;
+                        return Ok(close_document(
+                            &mut layer_stack,
+                            last_layer,
+                            diagnostics,
+                            end,
+                        ));
                     }
-
-                    if !matches!(n.unwrap().1, tag_closer!()) {
-                        return Err(ParserError {
-                            char: i,
-                            error_type: UnexpectedCharacter {
+                    Some((_, tag_closer!())) => {}
+                    Some((n, _)) => {
+                        diagnostics.report(ParserError::new(
+                            n,
+                            UnexpectedCharacter {
                                 expected: vec!['>'],
                             },
-                        });
+                        ))?;
+
+                        // Closing tags hold nothing: skip to their end
+                        if source.find(|(_, c)| matches!(c, tag_closer!())).is_none() {
+                            diagnostics.report(ParserError::eof())?;
+                            return Ok(close_document(
+                                &mut layer_stack,
+                                last_layer,
+                                diagnostics,
+                                end,
+                            ));
+                        }
                     }
                 }
 
                 // Find matching opening tag to close
-                loop {
-                    match layer_stack.last() {
-                        Some((_, last_node)) if last_node.borrow().name() == closer_name => {
-                            // Found matching tag - close it
+                match layer_stack
+                    .iter()
+                    .rposition(|(_, node)| node.name() == closer_name)
+                {
+                    None if closer_name == "p" => {
+                        // A stray </p> creates an empty paragraph, as in browsers
+                        diagnostics.warn(ParserError::new(tag_start, UnmatchedClosingTag));
+                        last_layer = close_implied(&mut layer_stack, last_layer, "p");
+
+                        last_layer.push(T::element(BorrowedNode::new(closer_name), vec![]));
+                    }
+                    None => {
+                        // No matching opening tag found: the closing tag is ignored
+                        diagnostics.report(ParserError::new(tag_start, UnmatchedClosingTag))?;
+                    }
+                    Some(position) => {
+                        // Close every element opened after the matching one
+                        while layer_stack.len() > position + 1 {
+                            let name = layer_stack.last().unwrap().1.name().to_string();
+
+                            if !__SELF_CLOSED.contains(&name.as_str())
+                                && !__OPTIONAL_END.contains(&name.as_str())
+                            {
+                                // The strict parser only closes elements whose end tag may be left out
+                                let error = match diagnostics.lenient {
+                                    true => UnclosedTag { name },
+                                    false => UnmatchedClosingTag,
+                                };
+                                diagnostics.report(ParserError::new(tag_start, error))?;
+                            }
+
                             last_layer = fold(&mut layer_stack, last_layer).unwrap();
-                            break;
-                        }
-                        Some((_, last_node)) if is_self_closable(last_node.borrow().name()) => {
-                            // Previous tag is self-closable - close it and try the one before
-                            last_layer =
-                                fold(&mut layer_stack, last_layer).map_err(|_| ParserError {
-                                    char: i,
-                                    error_type: UnmatchedClosingTag,
-                                })?;
-                        }
-                        _ => {
-                            // No matching opening tag found
-                            return Err(ParserError {
-                                char: i,
-                                error_type: UnmatchedClosingTag,
-                            });
                         }
+
+                        // Found matching tag - close it
+                        last_layer = fold(&mut layer_stack, last_layer).unwrap();
                     }
                 }
             }
@@ -526,19 +1287,33 @@ pub fn parse_html(document: &str) -> Result<Vec<HTMLEnum>, ParserError> {
             tag_name_starter_pattern!() => {
                 // Push any text before this tag
                 if tag_start != text_start {
-                    last_layer.push(HTMLEnum::Text(String::from(
-                        &document[text_start..tag_start],
-                    )));
+                    last_layer.push(text_token(&document[text_start..tag_start]));
                 }
                 text_used = true;
 
-                let (_source, from, to) = consume_tag_name(source)?;
-                source = _source;
+                let (from, to) = match consume_tag_name(source.clone()) {
+                    Ok((_source, from, to)) => {
+                        source = _source;
+                        (from, to)
+                    }
+                    Err(error) => {
+                        // The tag is cut by the end of the document: drop it
+                        diagnostics.report(error)?;
+                        return Ok(close_document(
+                            &mut layer_stack,
+                            last_layer,
+                            diagnostics,
+                            end,
+                        ));
+                    }
+                };
 
-                let node = HTMLNode::new();
-                let mut node_borrow = node.borrow_mut();
-                node_borrow.name = String::from(&document[from..to]).to_lowercase();
+                let mut node = BorrowedNode::new(lowercase(&document[from..to]));
+                node.source_position = Some(locator.locate(document, tag_start));
+                let in_svg =
+                    node.name() == "svg" || layer_stack.iter().any(|(_, x)| x.name() == "svg");
                 let mut closed: bool = false;
+                let mut malformed: Vec<&str> = vec![];
 
                 // Parse tag attributes
                 loop {
@@ -546,73 +1321,115 @@ pub fn parse_html(document: &str) -> Result<Vec<HTMLEnum>, ParserError> {
 
                     match source.peek() {
                         None => {
-                            return Err(ParserError {
-                                char: 0,
-                                error_type: ParserErrorType::UnexpectedEOF,
-                            });
+                            // The tag is cut by the end of the document: drop it
+                            diagnostics.report(ParserError::eof())?;
+                            return Ok(close_document(
+                                &mut layer_stack,
+                                last_layer,
+                                diagnostics,
+                                end,
+                            ));
                         }
                         Some((_, tag_closer!())) => {
                             source.next();
                             break;
                         }
                         Some((i, _)) if closed => {
-                            return Err(ParserError {
-                                char: *i,
-                                error_type: UnexpectedCharacter {
+                            // The slash was misplaced: ignore it
+                            diagnostics.report(ParserError::new(
+                                *i,
+                                UnexpectedCharacter {
                                     expected: vec!['>'],
                                 },
-                            });
+                            ))?;
+                            closed = false;
                         }
                         Some((_, tag_self_closer!())) => {
                             closed = true;
                             source.next();
                         }
-                        Some((_, _)) => {
-                            let (_source, attr, val) = consume_attr_value(source, document)?;
-                            source = _source;
-                            node_borrow.attribute(attr.to_string(), val);
-                        }
+                        Some(&(offset, _)) => match consume_attr_value(source.clone(), document) {
+                            Ok((_source, attr, val)) => {
+                                source = _source;
+                                add_attribute(&mut node, (attr, val), in_svg, offset, diagnostics);
+                            }
+                            Err(error) => {
+                                let eof = matches!(error.error_type, UnexpectedEOF);
+                                diagnostics.report(error)?;
+
+                                match consume_raw_attribute(source.clone(), document) {
+                                    Ok((_source, text)) if !eof => {
+                                        source = _source;
+                                        malformed.push(text);
+                                    }
+                                    _ => {
+                                        // The tag is cut by the end of the document: drop it
+                                        return Ok(close_document(
+                                            &mut layer_stack,
+                                            last_layer,
+                                            diagnostics,
+                                            end,
+                                        ));
+                                    }
+                                }
+                            }
+                        },
                     }
                 }
 
+                // Close the elements this one implicitly ends
+                last_layer = close_implied(&mut layer_stack, last_layer, node.name());
+
+                // Malformed attributes are kept as literal text, in front of their element
+                if !malformed.is_empty() {
+                    last_layer.push(T::text(Cow::Owned(malformed.join(" "))));
+                }
+
+                // The children already known: the raw text of quick-parse tags
+                let mut contents = vec![];
+
                 // Handle quick-parse tags (like <script>, <style>)
-                if __QUICKPARSE.contains(&node_borrow.name()) {
+                if __QUICKPARSE.contains(&node.name()) {
                     let i = match source.peek() {
                         None => {
-                            return Err(ParserError {
-                                char: 0,
-                                error_type: ParserErrorType::UnexpectedEOF,
-                            });
+                            diagnostics.report(ParserError::eof())?;
+                            last_layer.push(T::element(node, contents));
+                            return Ok(close_document(
+                                &mut layer_stack,
+                                last_layer,
+                                diagnostics,
+                                end,
+                            ));
                         }
-                        Some((i, _)) => i.clone(),
+                        Some((i, _)) => *i,
                     };
 
-                    match find_word(&mut source, &format!("</{}>", node_borrow.name())) {
+                    match find_word(&mut source, &format!("</{}>", node.name())) {
                         None => {
                             // The rest of the document is text as a child
-                            node_borrow.add_text(String::from(&document[i..]));
-                            drop(node_borrow);
-                            last_layer.push(HTMLEnum::Node(node));
-                            return Ok(fold_all(&mut layer_stack, last_layer));
+                            contents.push(T::text(Cow::Borrowed(&document[i..])));
+                            last_layer.push(T::element(node, contents));
+                            return Ok(close_document(
+                                &mut layer_stack,
+                                last_layer,
+                                diagnostics,
+                                end,
+                            ));
                         }
                         Some(index) => {
                             // Consume characters up to the closing tag
-                            node_borrow.add_text(String::from(&document[i..index]));
-                            drop(node_borrow);
+                            contents.push(T::text(Cow::Borrowed(&document[i..index])));
                         }
                     }
-                } else {
-                    // Drop the mutable borrow since we're done editing attributes
-                    drop(node_borrow);
                 }
 
                 if !closed {
                     // Node is not self-closed - make it a parent for subsequent nodes
                     layer_stack.push((last_layer, node));
-                    last_layer = vec![];
+                    last_layer = contents;
                 } else {
                     // Node is self-closed - add it to current layer
-                    last_layer.push(HTMLEnum::Node(node));
+                    last_layer.push(T::element(node, contents));
                 }
 
                 continue;
@@ -624,67 +1441,54 @@ pub fn parse_html(document: &str) -> Result<Vec<HTMLEnum>, ParserError> {
                 let doc = "doctype";
                 let com = "--";
 
-                if com.chars().zip(source.clone()).all(|(x, (_, y))| x == y) {
+                // Both openers must be there in full: a `<!` cut by the end of the document is text
+                if document[i + 1..].starts_with(com) {
                     // Handle comments
-                    source.nth(com.len());
+                    source.nth(com.len() - 1);
 
                     // Push any text before this comment
                     if tag_start != text_start {
-                        last_layer.push(HTMLEnum::Text(String::from(
-                            &document[text_start..tag_start],
-                        )));
+                        last_layer.push(text_token(&document[text_start..tag_start]));
                     }
 
-                    match find_word(&mut source.clone(), "-->") {
+                    match find_word(&mut source, "-->") {
                         None => {
                             // Unterminated comment - push the entire remaining document
-                            last_layer.push(HTMLEnum::Comment(String::from(&document[i + 3..])));
-                            return Ok(fold_all(&mut layer_stack, last_layer));
+                            last_layer.push(T::comment(&document[i + 3..]));
+                            return Ok(close_document(
+                                &mut layer_stack,
+                                last_layer,
+                                diagnostics,
+                                end,
+                            ));
                         }
                         Some(index) => {
-                            // Push the comment content
-                            last_layer
-                                .push(HTMLEnum::Comment(String::from(&document[i + 3..index])));
-                            source.nth(index);
+                            // Push the comment content, and skip the comment closer
+                            last_layer.push(T::comment(&document[i + 3..index]));
+                            source.nth("-->".len() - 1);
                             continue;
                         }
                     }
-                } else if doc.chars().zip(source.clone()).all(|(x, (_, y))| {
-                    y.to_lowercase().count() == 1 && y.to_lowercase().nth(0).unwrap() == x
-                }) {
+                } else if document
+                    .get(i + 1..i + 1 + doc.len())
+                    .is_some_and(|x| x.eq_ignore_ascii_case(doc))
+                {
                     // Handle DOCTYPE
-                    source.nth(doc.len());
+                    source.nth(doc.len() - 1);
 
                     // Push any text before this DOCTYPE
                     if tag_start != text_start {
-                        last_layer.push(HTMLEnum::Text(String::from(
-                            &document[text_start..tag_start],
-                        )));
+                        last_layer.push(text_token(&document[text_start..tag_start]));
                     }
 
-                    // Parse DOCTYPE attributes
-                    loop {
-                        source = consume_whitespaces(source);
-                        let mut closed: bool = false;
+                    let (_source, doctype, error) = consume_doctype(source, document);
+                    source = _source;
 
-                        match source.peek() {
-                            Some((_, tag_closer!())) => {
-                                source.next();
-                                break;
-                            }
-                            _ if closed => {
-                                todo!("Invalid char")
-                            }
-                            Some((_, tag_self_closer!())) => {
-                                closed = true;
-                            }
-                            _ => {
-                                let (_source, attr, val) = consume_attr_value(source, document)?;
-                                source = _source;
-                                doctype_attributes.push((attr, val));
-                            }
-                        }
+                    if let Some(error) = error {
+                        diagnostics.report(error)?;
                     }
+
+                    last_layer.push(T::doctype(doctype));
                 } else {
                     text_used = false;
                 }
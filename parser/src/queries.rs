@@ -1,4 +1,751 @@
-use crate::parse;
-use dom::html_elements;
+use crate::errors::ParserError;
+use crate::errors::ParserErrorType::*;
+use crate::parse::consume_whitespaces;
+use crate::{equal_sign, quotes_symbol, whitespace};
+use dom::hqueries::{
+    HAttributeOperator, HChildType, HCombinedQuery, HNth, HPseudoClass, HQuery, HQueryList,
+};
+use std::iter::Peekable;
+use std::str::CharIndices;
 
-pub fn parse_simple(source: &str) -> HQuery {}
+//What characters can start a CSS identifier
+#[macro_export]
+macro_rules! ident_starter_pattern {
+    () => { 'A' ..= 'Z' | 'a' ..= 'z' | '_' | '-' | '\u{80}' ..= char::MAX };
+}
+
+//What characters can be used inside a CSS identifier (except first character)
+#[macro_export]
+macro_rules! ident_pattern {
+    () => { 'A' ..= 'Z' | 'a' ..= 'z' | '0' ..= '9' | '_' | '-' | '\u{80}' ..= char::MAX };
+}
+
+/// Escapes the next character of an identifier or string (\.)
+#[macro_export]
+macro_rules! escape_symbol {
+    () => {
+        '\\'
+    };
+}
+
+/// Matches any element (*)
+#[macro_export]
+macro_rules! universal_selector {
+    () => {
+        '*'
+    };
+}
+
+/// Starts a class selector (.)
+#[macro_export]
+macro_rules! class_indicator {
+    () => {
+        '.'
+    };
+}
+
+/// Starts an ID selector (#)
+#[macro_export]
+macro_rules! id_indicator {
+    () => {
+        '#'
+    };
+}
+
+/// Opens an attribute selector ([)
+#[macro_export]
+macro_rules! attribute_opener {
+    () => {
+        '['
+    };
+}
+
+/// Closes an attribute selector (])
+#[macro_export]
+macro_rules! attribute_closer {
+    () => {
+        ']'
+    };
+}
+
+/// Separates a query from one on its direct children (>)
+#[macro_export]
+macro_rules! child_combinator {
+    () => {
+        '>'
+    };
+}
+
+/// Separates a query from one on the element right after it (+)
+#[macro_export]
+macro_rules! adjacent_combinator {
+    () => {
+        '+'
+    };
+}
+
+/// Separates a query from one on the elements after it (~)
+#[macro_export]
+macro_rules! sibling_combinator {
+    () => {
+        '~'
+    };
+}
+
+/// Starts a pseudo-class (:)
+#[macro_export]
+macro_rules! pseudo_class_indicator {
+    () => {
+        ':'
+    };
+}
+
+/// Opens the argument of a functional pseudo-class (()
+#[macro_export]
+macro_rules! argument_opener {
+    () => {
+        '('
+    };
+}
+
+/// Closes the argument of a functional pseudo-class ())
+#[macro_export]
+macro_rules! argument_closer {
+    () => {
+        ')'
+    };
+}
+
+/// Separates the queries of a list (,)
+#[macro_export]
+macro_rules! list_separator {
+    () => {
+        ','
+    };
+}
+
+type Source<'a> = Peekable<CharIndices<'a>>;
+
+/// Parses a selector made of a single compound selector, such as `div.card#main[data-x="1"]`.
+///
+/// ### Errors
+/// Returns an error pointing at the first character that cannot be part of the selector.
+/// For more information, please refer to [ParserError]
+pub fn parse_simple(source: &str) -> Result<HQuery, ParserError> {
+    parse_selector(source).map_err(|error| error.locate(source))
+}
+
+/// Parses a selector, leaving the error's line and column unset
+fn parse_selector(source: &str) -> Result<HQuery, ParserError> {
+    let characters = consume_whitespaces(source.char_indices().peekable());
+    let (characters, query) = consume_compound(characters)?;
+    let mut characters = consume_whitespaces(characters);
+
+    match characters.peek() {
+        None => Ok(query),
+        Some((i, _)) => Err(ParserError::new(
+            *i,
+            UnexpectedCharacter {
+                expected: vec![
+                    class_indicator!(),
+                    id_indicator!(),
+                    attribute_opener!(),
+                    pseudo_class_indicator!(),
+                ],
+            },
+        )),
+    }
+}
+
+/// Parses a selector made of compound selectors separated by combinators, such as `ul > li a` or `h2 + p`.
+///
+/// ### Errors
+/// Returns an error pointing at the first character that cannot be part of the selector.
+/// For more information, please refer to [ParserError]
+pub fn parse_combined(source: &str) -> Result<HCombinedQuery, ParserError> {
+    parse_combined_selector(source).map_err(|error| error.locate(source))
+}
+
+/// Parses a combined selector, leaving the error's line and column unset
+fn parse_combined_selector(source: &str) -> Result<HCombinedQuery, ParserError> {
+    let characters = consume_whitespaces(source.char_indices().peekable());
+    let (mut characters, query) = consume_combined(characters)?;
+
+    match characters.peek() {
+        None => Ok(query),
+        Some((i, _)) => Err(ParserError::new(*i, InvalidName)),
+    }
+}
+
+/// Parses a comma-separated list of combined selectors, such as `h1, h2 + p, .note > a`.
+///
+/// ### Errors
+/// Returns an error pointing at the first character that cannot be part of the selector.
+/// For more information, please refer to [ParserError]
+pub fn parse_list(source: &str) -> Result<HQueryList, ParserError> {
+    parse_list_selector(source).map_err(|error| error.locate(source))
+}
+
+/// Parses a selector list, leaving the error's line and column unset
+fn parse_list_selector(source: &str) -> Result<HQueryList, ParserError> {
+    let (mut characters, list) = consume_list(source.char_indices().peekable())?;
+
+    match characters.peek() {
+        None => Ok(list),
+        Some((i, _)) => Err(ParserError::new(*i, InvalidName)),
+    }
+}
+
+/// Consumes comma-separated combined selectors, until the end of the source or a closing parenthesis
+fn consume_list(mut characters: Source) -> Result<(Source, HQueryList), ParserError> {
+    let mut list = HQueryList::new();
+
+    loop {
+        let (rest, query) = consume_combined(consume_whitespaces(characters))?;
+        characters = rest;
+        list = list.or(query);
+
+        match characters.peek() {
+            Some((_, list_separator!())) => {
+                characters.next();
+            }
+            _ => return Ok((characters, list)),
+        }
+    }
+}
+
+/// Consumes the comma-separated relative selectors of `:has()`, each starting with an optional combinator
+fn consume_relative_list(
+    mut characters: Source,
+) -> Result<(Source, Vec<(HChildType, HCombinedQuery)>), ParserError> {
+    let mut list = vec![];
+
+    loop {
+        characters = consume_whitespaces(characters);
+
+        let child_type = match characters.peek() {
+            Some((_, child_combinator!())) => Some(HChildType::Direct),
+            Some((_, adjacent_combinator!())) => Some(HChildType::Adjacent),
+            Some((_, sibling_combinator!())) => Some(HChildType::Sibling),
+            _ => None,
+        };
+
+        if child_type.is_some() {
+            characters.next();
+        }
+
+        let (rest, query) = consume_combined(consume_whitespaces(characters))?;
+        characters = rest;
+        list.push((child_type.unwrap_or(HChildType::Indirect), query));
+
+        match characters.peek() {
+            Some((_, list_separator!())) => {
+                characters.next();
+            }
+            _ => return Ok((characters, list)),
+        }
+    }
+}
+
+/// Consumes compound selectors separated by combinators,
+/// until the end of the source, a comma, or the closing parenthesis of a pseudo-class argument
+fn consume_combined(characters: Source) -> Result<(Source, HCombinedQuery), ParserError> {
+    let (mut characters, root) = consume_compound(characters)?;
+    let mut query = HCombinedQuery::new(root);
+
+    loop {
+        let before = characters.peek().map(|(i, _)| *i);
+        characters = consume_whitespaces(characters);
+
+        let child_type = match characters.peek() {
+            None | Some((_, argument_closer!() | list_separator!())) => {
+                return Ok((characters, query))
+            }
+            Some((
+                _,
+                combinator @ (child_combinator!() | adjacent_combinator!() | sibling_combinator!()),
+            )) => {
+                let child_type = match combinator {
+                    child_combinator!() => HChildType::Direct,
+                    adjacent_combinator!() => HChildType::Adjacent,
+                    _ => HChildType::Sibling,
+                };
+
+                characters.next();
+                characters = consume_whitespaces(characters);
+                child_type
+            }
+            // Whitespace alone separates a query from one on its descendants
+            Some((i, _)) if Some(*i) != before => HChildType::Indirect,
+            Some((i, _)) => {
+                return Err(ParserError::new(
+                    *i,
+                    UnexpectedCharacter {
+                        expected: vec![
+                            class_indicator!(),
+                            id_indicator!(),
+                            attribute_opener!(),
+                            pseudo_class_indicator!(),
+                            child_combinator!(),
+                            adjacent_combinator!(),
+                            sibling_combinator!(),
+                        ],
+                    },
+                ))
+            }
+        };
+
+        let (rest, compound) = consume_compound(characters)?;
+        characters = rest;
+        query = query.then(child_type, compound);
+    }
+}
+
+/// Consumes a compound selector: an optional tag name, followed by classes, IDs, attribute selectors and pseudo-classes.
+/// At least one of them must be present
+fn consume_compound(mut characters: Source) -> Result<(Source, HQuery), ParserError> {
+    let mut query = HQuery::new();
+    let mut empty = true;
+
+    match characters.peek() {
+        Some((_, universal_selector!())) => {
+            characters.next();
+            empty = false;
+        }
+        Some((_, ident_starter_pattern!() | escape_symbol!())) => {
+            let (rest, name) = consume_identifier(characters)?;
+            characters = rest;
+            query = query.name(&name);
+            empty = false;
+        }
+        _ => {}
+    }
+
+    loop {
+        match characters.peek() {
+            Some((_, class_indicator!())) => {
+                characters.next();
+                let (rest, class) = consume_identifier(characters)?;
+                characters = rest;
+                query = query.class(&class);
+            }
+            Some((_, id_indicator!())) => {
+                characters.next();
+                let (rest, id) = consume_identifier(characters)?;
+                characters = rest;
+                query = query.id(&id);
+            }
+            Some((_, attribute_opener!())) => {
+                characters.next();
+                let (rest, attribute_query) = consume_attribute_selector(characters, query)?;
+                characters = rest;
+                query = attribute_query;
+            }
+            Some((_, pseudo_class_indicator!())) => {
+                characters.next();
+                let (rest, pseudo_class) = consume_pseudo_class(characters)?;
+                characters = rest;
+                query = query.pseudo_class(pseudo_class);
+            }
+            _ => break,
+        }
+
+        empty = false;
+    }
+
+    if empty {
+        return match characters.peek() {
+            None => Err(ParserError::eof()),
+            Some((i, _)) => Err(ParserError::new(*i, InvalidName)),
+        };
+    }
+
+    Ok((characters, query))
+}
+
+/// Consumes the inside of an attribute selector, after its opening bracket, and adds it to the query:
+/// `name]`, or `name`, an operator such as `=` or `^=`, a value, and an optional `i` or `s` flag, then `]`
+fn consume_attribute_selector(
+    characters: Source,
+    query: HQuery,
+) -> Result<(Source, HQuery), ParserError> {
+    let mut characters = consume_whitespaces(characters);
+
+    let attribute = match characters.peek() {
+        Some((_, ident_starter_pattern!() | escape_symbol!())) => {
+            let (rest, attribute) = consume_identifier(characters)?;
+            characters = rest;
+            attribute.to_lowercase()
+        }
+        Some((i, _)) => return Err(ParserError::new(*i, InvalidAttribute)),
+        None => return Err(ParserError::eof()),
+    };
+
+    characters = consume_whitespaces(characters);
+
+    let operator = match characters.next() {
+        Some((_, attribute_closer!())) => {
+            return Ok((characters, query.attribute(&attribute, None)))
+        }
+        Some((_, equal_sign!())) => HAttributeOperator::Equals,
+        Some((_, x @ ('^' | '$' | '*' | '~' | '|'))) => {
+            match characters.next() {
+                Some((_, equal_sign!())) => {}
+                Some((i, _)) => {
+                    return Err(ParserError::new(
+                        i,
+                        UnexpectedCharacter {
+                            expected: vec![equal_sign!()],
+                        },
+                    ))
+                }
+                None => return Err(ParserError::eof()),
+            }
+
+            match x {
+                '^' => HAttributeOperator::Prefix,
+                '$' => HAttributeOperator::Suffix,
+                '*' => HAttributeOperator::Substring,
+                '~' => HAttributeOperator::Includes,
+                _ => HAttributeOperator::DashMatch,
+            }
+        }
+        Some((i, _)) => {
+            return Err(ParserError::new(
+                i,
+                UnexpectedCharacter {
+                    expected: vec![attribute_closer!(), equal_sign!(), '^', '$', '*', '~', '|'],
+                },
+            ))
+        }
+        None => return Err(ParserError::eof()),
+    };
+
+    characters = consume_whitespaces(characters);
+
+    let (rest, value) = consume_value(characters)?;
+    characters = consume_whitespaces(rest);
+
+    // The case-sensitivity flag
+    let case_insensitive = match characters.peek() {
+        Some((_, 'i' | 'I')) => {
+            characters.next();
+            true
+        }
+        Some((_, 's' | 'S')) => {
+            characters.next();
+            false
+        }
+        _ => false,
+    };
+
+    characters = consume_whitespaces(characters);
+
+    match characters.next() {
+        Some((_, attribute_closer!())) => Ok((
+            characters,
+            query.attribute_matching(&attribute, operator, &value, case_insensitive),
+        )),
+        Some((i, _)) => Err(ParserError::new(
+            i,
+            UnexpectedCharacter {
+                expected: vec![attribute_closer!()],
+            },
+        )),
+        None => Err(ParserError::eof()),
+    }
+}
+
+/// Consumes a pseudo-class, after its colon, along with its argument if it takes one
+fn consume_pseudo_class(characters: Source) -> Result<(Source, HPseudoClass), ParserError> {
+    let start = match characters.clone().peek() {
+        Some((i, _)) => *i,
+        None => return Err(ParserError::eof()),
+    };
+
+    let (mut characters, name) = consume_identifier(characters)?;
+    let name = name.to_ascii_lowercase();
+
+    let pseudo_class = match name.as_str() {
+        "first-child" => HPseudoClass::FirstChild,
+        "last-child" => HPseudoClass::LastChild,
+        "only-child" => HPseudoClass::OnlyChild,
+        "first-of-type" => HPseudoClass::FirstOfType,
+        "last-of-type" => HPseudoClass::LastOfType,
+        "only-of-type" => HPseudoClass::OnlyOfType,
+        "empty" => HPseudoClass::Empty,
+        "root" => HPseudoClass::Root,
+        "nth-child" | "nth-last-child" | "nth-of-type" | "nth-last-of-type" => {
+            characters = consume_argument_opener(characters)?;
+
+            let (rest, nth) = consume_nth(consume_whitespaces(characters))?;
+            characters = consume_whitespaces(rest);
+
+            // Only :nth-child and :nth-last-child can count a subset of their siblings
+            let mut of = None;
+            if name.ends_with("child")
+                && characters
+                    .peek()
+                    .is_some_and(|(_, x)| matches!(x, 'o' | 'O'))
+            {
+                let (rest, keyword) = consume_identifier(characters.clone())?;
+
+                if keyword.eq_ignore_ascii_case("of") {
+                    let (rest, list) = consume_list(rest)?;
+                    characters = rest;
+                    of = Some(list);
+                }
+            }
+
+            characters = consume_argument_closer(characters)?;
+
+            match name.as_str() {
+                "nth-child" => HPseudoClass::NthChild(nth, of),
+                "nth-last-child" => HPseudoClass::NthLastChild(nth, of),
+                "nth-of-type" => HPseudoClass::NthOfType(nth),
+                _ => HPseudoClass::NthLastOfType(nth),
+            }
+        }
+        "not" | "is" | "where" => {
+            let (rest, list) = consume_list(consume_argument_opener(characters)?)?;
+            characters = consume_argument_closer(rest)?;
+
+            match name.as_str() {
+                "not" => HPseudoClass::Not(list),
+                "is" => HPseudoClass::Is(list),
+                _ => HPseudoClass::Where(list),
+            }
+        }
+        "has" => {
+            let (rest, list) = consume_relative_list(consume_argument_opener(characters)?)?;
+            characters = consume_argument_closer(rest)?;
+            HPseudoClass::Has(list)
+        }
+        _ => return Err(ParserError::new(start, UnknownPseudoClass { name })),
+    };
+
+    Ok((characters, pseudo_class))
+}
+
+/// Consumes the opening parenthesis of a pseudo-class' argument
+fn consume_argument_opener(mut characters: Source) -> Result<Source, ParserError> {
+    match characters.next() {
+        Some((_, argument_opener!())) => Ok(characters),
+        Some((i, _)) => Err(ParserError::new(
+            i,
+            UnexpectedCharacter {
+                expected: vec![argument_opener!()],
+            },
+        )),
+        None => Err(ParserError::eof()),
+    }
+}
+
+/// Consumes the closing parenthesis of a pseudo-class' argument, and the whitespace before it
+fn consume_argument_closer(characters: Source) -> Result<Source, ParserError> {
+    let mut characters = consume_whitespaces(characters);
+
+    match characters.next() {
+        Some((_, argument_closer!())) => Ok(characters),
+        Some((i, _)) => Err(ParserError::new(
+            i,
+            UnexpectedCharacter {
+                expected: vec![argument_closer!()],
+            },
+        )),
+        None => Err(ParserError::eof()),
+    }
+}
+
+/// Consumes an integer made of ASCII digits, if there is one
+fn consume_integer(mut characters: Source) -> (Source, Option<i32>) {
+    let mut value: Option<i32> = None;
+
+    while let Some(digit) = characters.peek().and_then(|(_, x)| x.to_digit(10)) {
+        value = Some(
+            value
+                .unwrap_or(0)
+                .saturating_mul(10)
+                .saturating_add(digit as i32),
+        );
+        characters.next();
+    }
+
+    (characters, value)
+}
+
+/// Consumes the `An+B` argument of a `:nth-*` pseudo-class, such as `odd`, `3`, `-n+2` or `2n - 1`
+fn consume_nth(mut characters: Source) -> Result<(Source, HNth), ParserError> {
+    let start = match characters.peek() {
+        Some((i, _)) => *i,
+        None => return Err(ParserError::eof()),
+    };
+
+    // odd and even are keywords, every other identifier is part of the An+B syntax
+    if let Some((_, 'o' | 'O' | 'e' | 'E')) = characters.peek() {
+        let (rest, keyword) = consume_identifier(characters)?;
+
+        return match keyword.to_ascii_lowercase().as_str() {
+            "odd" => Ok((rest, HNth { a: 2, b: 1 })),
+            "even" => Ok((rest, HNth { a: 2, b: 0 })),
+            _ => Err(ParserError::new(start, InvalidValue)),
+        };
+    }
+
+    let sign = match characters.peek() {
+        Some((_, '-')) => {
+            characters.next();
+            -1
+        }
+        Some((_, '+')) => {
+            characters.next();
+            1
+        }
+        _ => 1,
+    };
+
+    let (rest, coefficient) = consume_integer(characters);
+    characters = rest;
+
+    match characters.peek() {
+        Some((_, 'n' | 'N')) => {
+            characters.next();
+        }
+        // No n, the argument is only B
+        _ => {
+            return match coefficient {
+                Some(b) => Ok((characters, HNth { a: 0, b: sign * b })),
+                None => Err(ParserError::new(start, InvalidValue)),
+            }
+        }
+    }
+
+    let a = sign * coefficient.unwrap_or(1);
+
+    // Whitespace is allowed around the sign of B
+    let mut after_n = consume_whitespaces(characters.clone());
+
+    let sign = match after_n.peek() {
+        Some((_, '-')) => -1,
+        Some((_, '+')) => 1,
+        _ => return Ok((characters, HNth { a, b: 0 })),
+    };
+
+    after_n.next();
+    let (rest, b) = consume_integer(consume_whitespaces(after_n));
+
+    match b {
+        Some(b) => Ok((rest, HNth { a, b: sign * b })),
+        None => Err(ParserError::new(start, InvalidValue)),
+    }
+}
+
+/// Consumes an attribute selector's value, either quoted or written as an identifier
+fn consume_value(mut characters: Source) -> Result<(Source, String), ParserError> {
+    match characters.peek() {
+        Some((_, quotes_symbol!())) => consume_string(characters),
+        Some((_, ident_starter_pattern!() | escape_symbol!())) => consume_identifier(characters),
+        Some((i, _)) => Err(ParserError::new(*i, InvalidValue)),
+        None => Err(ParserError::eof()),
+    }
+}
+
+/// Consumes a quoted string, including its quotes, and returns its unescaped contents
+fn consume_string(mut characters: Source) -> Result<(Source, String), ParserError> {
+    let quote = match characters.next() {
+        Some((_, quote @ quotes_symbol!())) => quote,
+        Some((i, _)) => {
+            return Err(ParserError::new(
+                i,
+                UnexpectedCharacter {
+                    expected: vec!['"', '\''],
+                },
+            ))
+        }
+        None => return Err(ParserError::eof()),
+    };
+
+    let mut value = String::new();
+
+    loop {
+        match characters.next() {
+            Some((_, x)) if x == quote => return Ok((characters, value)),
+            Some((_, escape_symbol!())) => {
+                let (rest, unescaped) = consume_escape(characters)?;
+                characters = rest;
+                value.push(unescaped);
+            }
+            Some((_, x)) => value.push(x),
+            None => return Err(ParserError::eof()),
+        }
+    }
+}
+
+/// Consumes a CSS identifier (a tag name, class, ID or attribute name) and returns it unescaped
+fn consume_identifier(mut characters: Source) -> Result<(Source, String), ParserError> {
+    let mut identifier = String::new();
+
+    match characters.peek() {
+        Some((_, ident_starter_pattern!() | escape_symbol!())) => {}
+        Some((i, _)) => return Err(ParserError::new(*i, InvalidName)),
+        None => return Err(ParserError::eof()),
+    }
+
+    loop {
+        match characters.peek() {
+            Some((_, escape_symbol!())) => {
+                characters.next();
+                let (rest, unescaped) = consume_escape(characters)?;
+                characters = rest;
+                identifier.push(unescaped);
+            }
+            Some((_, x @ ident_pattern!())) => {
+                identifier.push(*x);
+                characters.next();
+            }
+            _ => break,
+        }
+    }
+
+    // A lone hyphen is not an identifier
+    match characters.peek() {
+        _ if identifier != "-" => Ok((characters, identifier)),
+        Some((i, _)) => Err(ParserError::new(*i, InvalidName)),
+        None => Err(ParserError::eof()),
+    }
+}
+
+/// Consumes an escape sequence, after its backslash.
+/// Either up to 6 hexadecimal digits followed by an optional whitespace, or any other single character
+fn consume_escape(mut characters: Source) -> Result<(Source, char), ParserError> {
+    let mut code: Option<u32> = None;
+
+    for _ in 0..6 {
+        match characters.peek().and_then(|(_, x)| x.to_digit(16)) {
+            Some(digit) => {
+                code = Some(code.unwrap_or(0) * 16 + digit);
+                characters.next();
+            }
+            None => break,
+        }
+    }
+
+    match code {
+        Some(code) => {
+            if let Some((_, whitespace!())) = characters.peek() {
+                characters.next();
+            }
+
+            let unescaped = match code {
+                0 => '\u{FFFD}',
+                code => char::from_u32(code).unwrap_or('\u{FFFD}'),
+            };
+
+            Ok((characters, unescaped))
+        }
+        None => match characters.next() {
+            Some((_, x)) => Ok((characters, x)),
+            None => Err(ParserError::eof()),
+        },
+    }
+}
@@ -0,0 +1,102 @@
+use dom::arena::{Arena, NodeData};
+use dom::html_elements::HTMLEnum;
+use parser::parse::parse_html;
+
+#[test]
+fn import_export() {
+    let source = "<div id=a><p class=x>Hello <b>world</b></p><!--c--><p>!</p></div><span>x</span>";
+    let nodes = parse_html(source).unwrap();
+    let (arena, roots) = Arena::from_nodes(&nodes);
+
+    assert_eq!(roots.len(), 2);
+    assert_eq!(arena.len(), 10);
+    assert_eq!(arena.get(roots[0]).name(), Some("div"));
+    assert_eq!(arena.element(roots[0]).unwrap().attribute("ID"), Some("a"));
+    assert_eq!(arena.text_content(roots[0]), "Hello world!");
+
+    let html: String = roots.iter().map(|x| arena.html(*x)).collect();
+    let expected: String = nodes.iter().map(HTMLEnum::to_string).collect();
+    assert_eq!(html, expected);
+
+    // Exported elements link back to their parent
+    let HTMLEnum::Node(div) = arena.export(roots[0]) else {
+        panic!("Expected a node")
+    };
+    let p = div.borrow().rec_html_children()[0].clone();
+    assert!(p.borrow().parent().is_some_and(|x| x == div));
+}
+
+#[test]
+fn links() {
+    let (arena, roots) =
+        Arena::from_nodes(&parse_html("<ul><li>1</li><li>2</li><li>3</li></ul>").unwrap());
+    let ul = roots[0];
+
+    let items: Vec<_> = arena.children(ul).collect();
+    assert_eq!(items.len(), 3);
+    assert_eq!(arena.get(ul).first_child(), Some(items[0]));
+    assert_eq!(arena.get(ul).last_child(), Some(items[2]));
+    assert_eq!(arena.get(items[1]).previous_sibling(), Some(items[0]));
+    assert_eq!(arena.get(items[1]).next_sibling(), Some(items[2]));
+    assert_eq!(arena.following_siblings(items[0]).count(), 2);
+
+    let text = arena.get(items[2]).first_child().unwrap();
+    assert_eq!(
+        arena.ancestors(text).collect::<Vec<_>>(),
+        vec![items[2], ul]
+    );
+    assert!(matches!(&arena[text], NodeData::Text(x) if x == "3"));
+
+    let names: Vec<_> = arena
+        .descendants(ul)
+        .filter_map(|x| arena.get(x).name())
+        .collect();
+    assert_eq!(names, vec!["li", "li", "li"]);
+    assert_eq!(arena.descendants(items[0]).count(), 1);
+}
+
+#[test]
+fn moving() {
+    let mut arena = Arena::new();
+    let div = arena.new_element("div");
+    let p = arena.new_element("p");
+    let span = arena.new_element("span");
+    let text = arena.new_text("x");
+
+    arena.append_child(div, p);
+    arena.append_child(div, span);
+    arena.append_child(p, text);
+    assert_eq!(arena.html(div), "<div><p>x</p><span></span></div>");
+
+    arena.append_child(span, text);
+    assert_eq!(arena.html(div), "<div><p></p><span>x</span></div>");
+
+    arena.append_child(div, p);
+    assert_eq!(arena.html(div), "<div><span>x</span><p></p></div>");
+    assert_eq!(arena.get(span).previous_sibling(), None);
+    assert_eq!(arena.get(div).last_child(), Some(p));
+
+    arena.detach(span);
+    assert_eq!(arena.html(div), "<div><p></p></div>");
+    assert_eq!(arena.get(span).parent(), None);
+    assert_eq!(arena.get(p).previous_sibling(), None);
+    assert_eq!(arena.html(span), "<span>x</span>");
+
+    arena
+        .element_mut(p)
+        .unwrap()
+        .attributes
+        .set("class".to_string(), Some("a".to_string()));
+    assert_eq!(arena.html(div), "<div><p class=\"a\"></p></div>");
+}
+
+#[test]
+#[should_panic]
+fn cycles() {
+    let mut arena = Arena::new();
+    let div = arena.new_element("div");
+    let p = arena.new_element("p");
+
+    arena.append_child(div, p);
+    arena.append_child(p, div);
+}
@@ -0,0 +1,83 @@
+use dom::attributes::Attributes;
+use dom::html_elements::HTMLEnum;
+use parser::errors::ParserErrorType;
+use parser::parse::{parse_html, parse_html_borrowed, parse_html_lenient};
+
+fn serialize(source: &str) -> String {
+    parse_html(source)
+        .unwrap()
+        .iter()
+        .map(HTMLEnum::to_string)
+        .collect()
+}
+
+#[test]
+fn order() {
+    assert_eq!(
+        serialize("<a z=1 href=x.html Title=\"t\" b data-y=2></a>"),
+        "<a z=\"1\" href=\"x.html\" title=\"t\" b data-y=\"2\"></a>"
+    );
+
+    let source = "<input value=\"x\" type=text name=q id=search disabled>";
+    assert_eq!(serialize(source), serialize(&serialize(source)));
+}
+
+#[test]
+fn duplicates() {
+    let source = "<p class=a id=x CLASS=b class=c>x</p>";
+    assert_eq!(serialize(source), "<p class=\"a\" id=\"x\">x</p>");
+
+    let (_, errors) = parse_html_lenient(source);
+    assert_eq!(errors.len(), 2);
+    assert!(
+        matches!(&errors[0].error_type, ParserErrorType::DuplicateAttribute { name } if name == "class")
+    );
+    assert_eq!(errors[0].column, 17);
+    assert_eq!(
+        errors[1].to_string().lines().next(),
+        Some("duplicate attribute class at line 1, column 25")
+    );
+}
+
+#[test]
+fn svg_case() {
+    assert_eq!(
+        serialize("<svg viewBox=\"0 0 10 10\" VIEWBOX=x><path pathLength=5 Fill=red /></svg>"),
+        "<svg viewBox=\"0 0 10 10\"><path pathLength=\"5\" fill=\"red\"></path></svg>"
+    );
+    assert_eq!(
+        serialize("<div viewBox=x></div>"),
+        "<div viewbox=\"x\"></div>"
+    );
+
+    match &parse_html_borrowed("<svg viewBox=x></svg>").unwrap()[0] {
+        dom::borrowed::BorrowedEnum::Node(node) => {
+            assert_eq!(node.get_attribute("viewBox"), Some(Some("x")))
+        }
+        other => panic!("Expected a node, got {:?}", other),
+    }
+}
+
+#[test]
+fn attributes() {
+    let mut attributes: Attributes = vec![
+        ("b".to_string(), None),
+        ("a".to_string(), Some("1".to_string())),
+        ("b".to_string(), Some("2".to_string())),
+    ]
+    .into_iter()
+    .collect();
+
+    assert_eq!(attributes.len(), 2);
+    assert_eq!(attributes.get("b"), Some(None));
+    assert!(!attributes.add("a".to_string(), None));
+    assert_eq!(
+        attributes.set("b".to_string(), Some("3".to_string())),
+        Some(None)
+    );
+    assert_eq!(attributes.set("c".to_string(), None), None);
+
+    let names: Vec<&str> = attributes.iter().map(|(x, _)| x.as_str()).collect();
+    assert_eq!(names, vec!["b", "a", "c"]);
+    assert_eq!(attributes.get("b"), Some(Some("3")));
+}
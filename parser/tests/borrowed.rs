@@ -0,0 +1,76 @@
+use dom::borrowed::BorrowedEnum;
+use dom::html_elements::HTMLEnum;
+use parser::parse::{parse_html, parse_html_borrowed};
+use std::borrow::Cow;
+
+fn first_node<'a>(tokens: &'a [BorrowedEnum<'a>]) -> &'a dom::borrowed::BorrowedNode<'a> {
+    match &tokens[0] {
+        BorrowedEnum::Node(node) => node,
+        other => panic!("Expected a node, got {:?}", other),
+    }
+}
+
+#[test]
+fn borrows_source() {
+    let source =
+        "<div class=card data-x=\"a&amp;b\"><P>Tom &amp; Jerry</P><!--c--><i>plain</i></div>";
+    let tokens = parse_html_borrowed(source).unwrap();
+    let div = first_node(&tokens);
+
+    assert!(matches!(div.name, Cow::Borrowed("div")));
+    assert_eq!(div.get_attribute("class"), Some(Some("card")));
+    assert!(matches!(div.attributes[0].1, Some(Cow::Borrowed("card"))));
+    assert!(matches!(div.attributes[1].1, Some(Cow::Owned(_))));
+    assert_eq!(div.get_attribute("data-x"), Some(Some("a&b")));
+
+    let p = match &div.children[0] {
+        BorrowedEnum::Node(node) => node,
+        other => panic!("Expected a node, got {:?}", other),
+    };
+    assert!(matches!(p.name, Cow::Owned(_)));
+    assert_eq!(p.name(), "p");
+    assert!(matches!(&p.children[0], BorrowedEnum::Text(Cow::Owned(x)) if x == "Tom & Jerry"));
+
+    assert!(matches!(div.children[1], BorrowedEnum::Comment("c")));
+    assert_eq!(div.text_content(), "Tom & Jerryplain");
+
+    // Borrowed slices point into the source itself
+    let range = source.as_bytes().as_ptr_range();
+    match &div.attributes[0].1 {
+        Some(Cow::Borrowed(value)) => assert!(range.contains(&value.as_ptr())),
+        other => panic!("Expected a borrowed value, got {:?}", other),
+    }
+}
+
+#[test]
+fn same_as_owned() {
+    let source = "<!DOCTYPE html><ul><li>1<li>2</ul><p>a<p>b</p><script>if (a < b) {}</script><br><img src=x.png>";
+    let borrowed = parse_html_borrowed(source).unwrap();
+    let owned = parse_html(source).unwrap();
+
+    let to_string =
+        |tokens: &[HTMLEnum]| tokens.iter().map(HTMLEnum::to_string).collect::<String>();
+    let borrowed_string: String = borrowed.iter().map(BorrowedEnum::to_string).collect();
+
+    assert_eq!(borrowed_string, to_string(&owned));
+    assert_eq!(
+        to_string(
+            &borrowed
+                .iter()
+                .map(BorrowedEnum::to_html_enum)
+                .collect::<Vec<_>>()
+        ),
+        to_string(&owned)
+    );
+}
+
+#[test]
+fn errors() {
+    assert!(parse_html_borrowed("<div></span>").is_err());
+    assert_eq!(
+        parse_html_borrowed("<div a=\"x></div>")
+            .unwrap_err()
+            .to_string(),
+        parse_html("<div a=\"x></div>").unwrap_err().to_string()
+    );
+}
@@ -0,0 +1,84 @@
+use dom::doctype::{Doctype, QuirksMode};
+use dom::document::HTMLDocument;
+use dom::html_elements::HTMLEnum;
+use parser::parse::{parse_html, parse_html_lenient};
+
+fn doctype(source: &str) -> Doctype {
+    match parse_html_lenient(source).0.into_iter().next() {
+        Some(HTMLEnum::Doctype(doctype)) => doctype,
+        other => panic!("Expected a doctype, got {:?}", other),
+    }
+}
+
+#[test]
+fn html5() {
+    let doctype = doctype("<!DOCTYPE html><p>x</p>");
+
+    assert_eq!(doctype, Doctype::html5());
+    assert_eq!(doctype.quirks_mode(), QuirksMode::NoQuirks);
+    assert_eq!(doctype.to_string(), "<!DOCTYPE html>");
+}
+
+#[test]
+fn identifiers() {
+    let doctype = doctype(
+        r#"<!doctype HTML public "-//W3C//DTD HTML 4.01 Transitional//EN" 'http://www.w3.org/TR/html4/loose.dtd'>"#,
+    );
+
+    assert_eq!(doctype.name, "html");
+    assert_eq!(
+        doctype.public_id.as_deref(),
+        Some("-//W3C//DTD HTML 4.01 Transitional//EN")
+    );
+    assert_eq!(
+        doctype.system_id.as_deref(),
+        Some("http://www.w3.org/TR/html4/loose.dtd")
+    );
+    assert_eq!(doctype.quirks_mode(), QuirksMode::LimitedQuirks);
+    assert_eq!(
+        doctype.to_string(),
+        r#"<!DOCTYPE html PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN" "http://www.w3.org/TR/html4/loose.dtd">"#
+    );
+}
+
+#[test]
+fn quirks() {
+    assert_eq!(
+        doctype(r#"<!DOCTYPE html PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN">"#).quirks_mode(),
+        QuirksMode::Quirks
+    );
+    assert_eq!(
+        doctype(r#"<!DOCTYPE html SYSTEM "about:legacy-compat">"#).quirks_mode(),
+        QuirksMode::NoQuirks
+    );
+    assert_eq!(doctype("<!DOCTYPE svg>").quirks_mode(), QuirksMode::Quirks);
+    assert_eq!(
+        doctype("<!DOCTYPE html bogus>").quirks_mode(),
+        QuirksMode::Quirks
+    );
+}
+
+#[test]
+fn malformed() {
+    assert!(parse_html("<!DOCTYPE html bogus><p>x</p>").is_err());
+
+    let (tree, errors) = parse_html_lenient("<!DOCTYPE><p>x</p>");
+    assert!(matches!(&tree[0], HTMLEnum::Doctype(x) if x.force_quirks));
+    assert!(matches!(&tree[1], HTMLEnum::Node(_)));
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn document() {
+    let document =
+        HTMLDocument::from_tokens(parse_html("<!DOCTYPE html><title>x</title><p>y</p>").unwrap());
+
+    assert_eq!(document.doctype(), Some(&Doctype::html5()));
+    assert_eq!(document.quirks_mode(), QuirksMode::NoQuirks);
+    assert!(document
+        .to_string()
+        .starts_with("<!DOCTYPE html><html><head><title>x</title></head>"));
+
+    let document = HTMLDocument::from_tokens(parse_html("<p>y</p>").unwrap());
+    assert_eq!(document.quirks_mode(), QuirksMode::Quirks);
+}
@@ -0,0 +1,273 @@
+use dom::document::{DocumentOptions, HTMLDocument};
+use dom::hqueries::{HQuery, HQueryErr};
+use dom::html_elements::HTMLNode;
+use parser::parse::{parse_html, ParseDocument};
+
+const SOURCE: &str = "<!DOCTYPE html><title>  Hello\n  world </title>\
+    <div class=\"a b\" id=main><p class=b>x</p><p>y</p></div>";
+
+#[test]
+fn accessors() {
+    let document = HTMLDocument::parse(SOURCE).unwrap();
+
+    assert_eq!(document.document_element().borrow().name(), "html");
+    assert_eq!(document.head().borrow().name(), "head");
+    assert_eq!(document.body().borrow().name(), "body");
+    assert_eq!(document.body().borrow().children().len(), 1);
+    assert!(document.head().borrow().parent().is_some());
+}
+
+#[test]
+fn title() {
+    let mut document = HTMLDocument::parse(SOURCE).unwrap();
+    assert_eq!(document.title(), "Hello world");
+
+    document.set_title("Changed");
+    assert_eq!(document.title(), "Changed");
+    assert_eq!(document.head().borrow().children().len(), 1);
+
+    let mut document = HTMLDocument::parse("<p>x</p>").unwrap();
+    assert_eq!(document.title(), "");
+
+    document.set_title("New");
+    assert_eq!(document.title(), "New");
+    assert!(document
+        .to_string()
+        .contains("<head><title>New</title></head>"));
+}
+
+#[test]
+fn set_title_detaches() {
+    let mut document = HTMLDocument::parse("<title>a <span id=s>b</span></title>").unwrap();
+    let span = document.get_element_by_id("s").unwrap();
+
+    document.set_title("New");
+    assert_eq!(document.title(), "New");
+    assert!(span.borrow().parent().is_none());
+    assert!(document.get_element_by_id("s").is_none());
+}
+
+#[test]
+fn query_selector() {
+    let document = HTMLDocument::parse(SOURCE).unwrap();
+
+    let main = document.query_selector(&HQuery::new().id("main")).unwrap();
+    assert_eq!(main.borrow().name(), "div");
+
+    let bs = document.query_selector_all(&HQuery::new().class("b"));
+    assert_eq!(bs.len(), 2);
+    assert_eq!(bs[1].borrow().name(), "p");
+
+    assert_eq!(
+        document.query_selector_all(&HQuery::new().name("p")).len(),
+        2
+    );
+    assert!(document
+        .query_selector(&HQuery::new().name("span"))
+        .is_none());
+}
+
+#[test]
+fn parse_errors() {
+    assert!(HTMLDocument::parse("<div></span>").is_err());
+}
+
+#[test]
+fn root_attributes() {
+    let document = HTMLDocument::parse(
+        "<html lang=en><head data-x=1></head><body class=home><p>x</p></body></html>",
+    )
+    .unwrap();
+
+    let html = document.document_element();
+    assert_eq!(
+        html.borrow().get_attribute("lang"),
+        Some(Some("en".to_string()))
+    );
+    assert_eq!(
+        document.head().borrow().get_attribute("data-x"),
+        Some(Some("1".to_string()))
+    );
+    assert_eq!(
+        document.body().borrow().get_attribute("class"),
+        Some(Some("home".to_string()))
+    );
+    assert_eq!(
+        document.to_string(),
+        "<html lang=\"en\"><head data-x=\"1\"></head><body class=\"home\"><p>x</p></body></html>"
+    );
+}
+
+#[test]
+fn merged_attributes() {
+    let (tokens, _) = parser::parse::parse_html_lenient(
+        "<html lang=en><body class=a><p>x</p><html lang=fr dir=ltr><body class=b id=c>",
+    );
+    let document = HTMLDocument::from_tokens(tokens);
+
+    let html = document.document_element();
+    assert_eq!(
+        html.borrow().get_attribute("lang"),
+        Some(Some("en".to_string()))
+    );
+    assert_eq!(
+        html.borrow().get_attribute("dir"),
+        Some(Some("ltr".to_string()))
+    );
+
+    let body = document.body();
+    assert_eq!(
+        body.borrow().get_attribute("class"),
+        Some(Some("a".to_string()))
+    );
+    assert_eq!(
+        body.borrow().get_attribute("id"),
+        Some(Some("c".to_string()))
+    );
+    assert_eq!(body.borrow().children().len(), 1);
+}
+
+fn with_comments(source: &str) -> String {
+    let options = DocumentOptions {
+        keep_comments: true,
+    };
+
+    HTMLDocument::from_tokens_with_options(parse_html(source).unwrap(), &options).to_string()
+}
+
+#[test]
+fn comments() {
+    assert_eq!(
+        with_comments("<!-- (c) ACME --><html><!--a--><head><!--b--></head><!--c--><body><p><!--d-->x</p></body><!--e--></html><!--f-->"),
+        "<!-- (c) ACME --><html><!--a--><head><!--b--></head><!--c--><body><p><!--d-->x</p></body><!--e--></html><!--f-->"
+    );
+    assert_eq!(
+        with_comments("<!--[if IE]><p>old</p><![endif]--><title>t</title><p>x</p>"),
+        "<!--[if IE]><p>old</p><![endif]--><html><head><title>t</title></head><body><p>x</p></body></html>"
+    );
+
+    let document = HTMLDocument::parse("<!--gone--><p><!--gone-->x</p>").unwrap();
+    assert_eq!(
+        document.to_string(),
+        "<html><head></head><body><p>x</p></body></html>"
+    );
+}
+
+#[test]
+fn whitespace() {
+    let source =
+        "\n<html>\n<head>\n<title>t</title>\n</head>\n<body>\n<p>x</p>\n</body>\n</html>\n";
+
+    assert_eq!(
+        HTMLDocument::parse(source).unwrap().to_string(),
+        "<html><head>\n<title>t</title>\n</head>\n<body>\n<p>x</p>\n\n\n</body></html>"
+    );
+    assert_eq!(
+        HTMLDocument::parse("<title>t</title> <meta charset=utf-8> <p>x</p>")
+            .unwrap()
+            .to_string(),
+        "<html><head><title>t</title> <meta charset=\"utf-8\"/> </head><body><p>x</p></body></html>"
+    );
+}
+
+#[test]
+fn id_index() {
+    let document =
+        HTMLDocument::parse("<p id=a>1</p><div id=b><span id=c></span></div><i id></i>").unwrap();
+
+    let index = document.id_index().unwrap();
+    assert_eq!(index.len(), 3);
+    assert_eq!(index["c"].borrow().name(), "span");
+
+    let document = HTMLDocument::parse("<p id=a></p><div><span id=a></span></div>").unwrap();
+    let err = document.id_index().unwrap_err();
+    assert!(matches!(&err, HQueryErr::DuplicateId { id, .. } if id == "a"));
+    assert_eq!(
+        err.to_string(),
+        "duplicate id \"a\" at line 1, column 1, line 1, column 18"
+    );
+}
+
+#[test]
+fn get_elements() {
+    let document = HTMLDocument::parse(
+        "<title>t</title><div id=main class=\"a b\"><p class=b>x</p><P class=\"b\ta\">y</P></div>",
+    )
+    .unwrap();
+
+    let main = document.get_element_by_id("main").unwrap();
+    assert_eq!(main.borrow().name(), "div");
+    assert!(document.get_element_by_id("Main").is_none());
+    assert!(document.get_element_by_id("").is_none());
+
+    assert_eq!(document.get_elements_by_class_name("b").len(), 3);
+    assert_eq!(document.get_elements_by_class_name(" a  b ").len(), 2);
+    assert!(document.get_elements_by_class_name(" ").is_empty());
+
+    assert_eq!(document.get_elements_by_tag_name("p").len(), 2);
+    assert_eq!(document.get_elements_by_tag_name("TITLE").len(), 1);
+    assert_eq!(document.get_elements_by_tag_name("*").len(), 7);
+}
+
+#[test]
+fn live_id_index() {
+    let document = HTMLDocument::parse("<div id=a><p id=b>x</p></div><html id=root>").unwrap();
+    assert!(document.get_element_by_id("root").is_some());
+
+    // Changing the id through the node
+    let p = document.get_element_by_id("b").unwrap();
+    p.borrow_mut()
+        .attribute("id".to_string(), Some("c".to_string()));
+    assert!(document.get_element_by_id("b").is_none());
+    assert!(document.get_element_by_id("c").unwrap() == p);
+
+    // Orphaning a subtree removes all of its ids
+    let div = document.get_element_by_id("a").unwrap();
+    div.borrow_mut().orphanize();
+    assert!(document.get_element_by_id("a").is_none());
+    assert!(document.get_element_by_id("c").is_none());
+
+    // Detached nodes are not indexed, until they are added back
+    p.borrow_mut()
+        .attribute("id".to_string(), Some("d".to_string()));
+    assert!(document.get_element_by_id("d").is_none());
+    document.body().borrow_mut().add_child(div.clone());
+    assert!(document.get_element_by_id("a").is_some());
+    assert!(document.get_element_by_id("d").is_some());
+
+    // Duplicates resolve to the first one in document order
+    let span = HTMLNode::new();
+    span.set_name("span");
+    span.borrow_mut()
+        .attribute("ID".to_string(), Some("a".to_string()));
+    document.head().borrow_mut().add_child(span.clone());
+    assert!(document.get_element_by_id("a").unwrap() == span);
+    assert_eq!(document.validate().len(), 1);
+}
+
+#[test]
+fn validate() {
+    let document = HTMLDocument::parse(
+        "<p id=x>1</p>\n<p id=y>2</p>\n  <div>\n    <span id=x></span><b id=y></b><i id=x></i>\n  </div>",
+    )
+    .unwrap();
+
+    let errors = document.validate();
+    assert_eq!(errors.len(), 2);
+
+    match &errors[0] {
+        HQueryErr::DuplicateId { id, positions } => {
+            assert_eq!(id, "x");
+
+            let lines: Vec<(usize, usize)> = positions.iter().map(|x| (x.line, x.column)).collect();
+            assert_eq!(lines, vec![(1, 1), (4, 5), (4, 35)]);
+            assert_eq!(positions[1].offset, 40);
+        }
+    }
+
+    assert!(matches!(&errors[1], HQueryErr::DuplicateId { id, .. } if id == "y"));
+    assert!(HTMLDocument::parse("<p id=x></p>")
+        .unwrap()
+        .validate()
+        .is_empty());
+}
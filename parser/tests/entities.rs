@@ -0,0 +1,65 @@
+use dom::html_elements::HTMLEnum;
+use parser::entities::decode_character_references;
+use parser::parse::parse_html;
+
+#[test]
+fn named_references() {
+    assert_eq!(
+        decode_character_references(
+            "&lt;p&gt; &amp;&nbsp;&CounterClockwiseContourIntegral;",
+            false
+        ),
+        "<p> &\u{a0}\u{2233}"
+    );
+}
+
+#[test]
+fn numeric_references() {
+    assert_eq!(
+        decode_character_references("&#x27;&#39;&#X41&#0;&#x110000;&#128;&#xD800;", false),
+        "''A\u{fffd}\u{fffd}\u{20ac}\u{fffd}"
+    );
+}
+
+#[test]
+fn legacy_references() {
+    assert_eq!(
+        decode_character_references("&copy 2024 &notit;", false),
+        "\u{a9} 2024 \u{ac}it;"
+    );
+    assert_eq!(
+        decode_character_references("?a=1&copy=2&notit;&amp", true),
+        "?a=1&copy=2&notit;&"
+    );
+}
+
+#[test]
+fn invalid_references() {
+    assert_eq!(
+        decode_character_references("& &# &#x; &foo;", false),
+        "& &# &#x; &foo;"
+    );
+}
+
+#[test]
+fn decoded_tree() {
+    let tree =
+        parse_html(r#"<a title="Tom &amp; Jerry" id=x&#45;y>&lt;b&gt;</a><script>&amp;</script>"#)
+            .unwrap();
+
+    let HTMLEnum::Node(a) = &tree[0] else {
+        panic!("Expected a node")
+    };
+    let a = a.borrow();
+    assert_eq!(
+        a.get_attribute("title"),
+        Some(Some("Tom & Jerry".to_string()))
+    );
+    assert_eq!(a.get_attribute("id"), Some(Some("x-y".to_string())));
+    assert!(matches!(&a.children()[0], HTMLEnum::Text(t) if t == "<b>"));
+
+    let HTMLEnum::Node(script) = &tree[1] else {
+        panic!("Expected a node")
+    };
+    assert!(matches!(&script.borrow().children()[0], HTMLEnum::Text(t) if t == "&amp;"));
+}
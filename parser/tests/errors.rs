@@ -0,0 +1,42 @@
+use parser::errors::ParserErrorType;
+use parser::parse::parse_html;
+
+#[test]
+fn unmatched_position() {
+    let err = parse_html("<div>\n  <p>héllo</span>\n</div>").unwrap_err();
+
+    assert!(matches!(
+        err.error_type,
+        ParserErrorType::UnmatchedClosingTag
+    ));
+    assert_eq!((err.char, err.line, err.column), (17, 2, 11));
+    assert_eq!(err.source_line, "  <p>héllo</span>");
+}
+
+#[test]
+fn eof_position() {
+    let err = parse_html("<p>\n<div class=\"x").unwrap_err();
+
+    assert!(matches!(err.error_type, ParserErrorType::UnexpectedEOF));
+    assert_eq!((err.char, err.line, err.column), (17, 2, 14));
+}
+
+#[test]
+fn display() {
+    let err = parse_html("<p>\r\n\t<b>x</i></p>").unwrap_err();
+
+    assert_eq!(
+        err.to_string(),
+        "unmatched closing tag at line 2, column 6\n2 | \t<b>x</i></p>\n  | \t    ^"
+    );
+}
+
+#[test]
+fn doctype_slash() {
+    let err = parse_html("<!DOCTYPE html/ x>").unwrap_err();
+
+    assert!(matches!(
+        err.error_type,
+        ParserErrorType::UnexpectedCharacter { .. }
+    ));
+}
@@ -0,0 +1,105 @@
+use dom::document::{DocumentOptions, HTMLDocument};
+use dom::frozen::FrozenDocument;
+use dom::hqueries::HQuery;
+use dom::html_elements::HTMLNode;
+use parser::parse::parse_html;
+use parser::parse::ParseDocument;
+use std::thread;
+
+const SOURCE: &str = "<!DOCTYPE html><!--top--><html lang=en><head><title> A  page </title></head>\
+    <body><div id=main><p class=x>Hello</p><!--c--><p id=main>world</p></div></body></html><!--bottom-->";
+
+fn document() -> HTMLDocument {
+    let options = DocumentOptions {
+        keep_comments: true,
+    };
+
+    HTMLDocument::from_tokens_with_options(parse_html(SOURCE).unwrap(), &options)
+}
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn send_sync() {
+    assert_send_sync::<FrozenDocument>();
+    assert_send_sync::<dom::arena::Arena>();
+}
+
+#[test]
+fn freeze() {
+    let document = document();
+    let frozen = document.freeze();
+
+    assert_eq!(frozen.to_string(), document.to_string());
+    assert_eq!(frozen.title(), "A page");
+    assert_eq!(frozen.doctype(), document.doctype());
+
+    let arena = frozen.arena();
+    assert_eq!(arena.get(frozen.head()).name(), Some("head"));
+    assert_eq!(
+        arena.get(frozen.body()).parent(),
+        Some(frozen.document_element())
+    );
+
+    let main = frozen.get_element_by_id("main").unwrap();
+    assert_eq!(arena.get(main).name(), Some("div"));
+    assert!(frozen.get_element_by_id("none").is_none());
+}
+
+#[test]
+fn thaw() {
+    let document = document();
+    let thawed = FrozenDocument::from(&document).thaw();
+
+    assert_eq!(thawed.to_string(), document.to_string());
+    assert_eq!(thawed.title(), "A page");
+    assert_eq!(
+        thawed.get_element_by_id("main").unwrap().borrow().name(),
+        "div"
+    );
+    assert_eq!(thawed.query_selector_all(&HQuery::new().name("p")).len(), 2);
+}
+
+#[test]
+fn thaw_in_place() {
+    let document = HTMLDocument::parse("<p>text</p>").unwrap();
+
+    for name in ["meta", "title"] {
+        let node = HTMLNode::new();
+        node.set_name(name);
+        document.body().borrow_mut().add_child(node);
+    }
+
+    let thawed = document.freeze().thaw();
+
+    assert_eq!(
+        thawed.to_string(),
+        "<html><head></head><body><p>text</p><meta/><title></title></body></html>"
+    );
+    assert_eq!(thawed.to_string(), document.to_string());
+}
+
+#[test]
+fn across_threads() {
+    let frozen = thread::spawn(|| HTMLDocument::parse(SOURCE).unwrap().freeze())
+        .join()
+        .unwrap();
+
+    let handlers: Vec<_> = (0..4)
+        .map(|_| {
+            let template = frozen.clone();
+            thread::spawn(move || {
+                let mut page = template.thaw();
+                page.set_title("Changed");
+                (template.title(), page.title())
+            })
+        })
+        .collect();
+
+    for handler in handlers {
+        assert_eq!(
+            handler.join().unwrap(),
+            ("A page".to_string(), "Changed".to_string())
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use parser::parse::parse_html;
+
+fn parse(source: &str) -> String {
+    parse_html(source)
+        .unwrap()
+        .iter()
+        .map(|x| x.to_string())
+        .collect()
+}
+
+#[test]
+fn paragraphs() {
+    assert_eq!(parse("<p>a<p>b"), "<p>a</p><p>b</p>");
+    assert_eq!(parse("<p>a<div>b</div>"), "<p>a</p><div>b</div>");
+    assert_eq!(parse("<p>a<span>b</span>c"), "<p>a<span>b</span>c</p>");
+    assert_eq!(parse("<div><p>a</div>b"), "<div><p>a</p></div>b");
+}
+
+#[test]
+fn stray_paragraph_end() {
+    assert_eq!(parse("<p>a<div>b</div></p>"), "<p>a</p><div>b</div><p></p>");
+}
+
+#[test]
+fn list_items() {
+    assert_eq!(
+        parse("<ul><li>a<li>b<ul><li>c<li>d</ul><li>e</ul>"),
+        "<ul><li>a</li><li>b<ul><li>c</li><li>d</li></ul></li><li>e</li></ul>"
+    );
+    assert_eq!(
+        parse("<dl><dt>a<dd>b<dt>c</dl>"),
+        "<dl><dt>a</dt><dd>b</dd><dt>c</dt></dl>"
+    );
+}
+
+#[test]
+fn options() {
+    assert_eq!(
+        parse("<select><optgroup><option>a<option>b<optgroup><option>c</select>"),
+        "<select><optgroup><option>a</option><option>b</option></optgroup><optgroup><option>c</option></optgroup></select>"
+    );
+}
+
+#[test]
+fn tables() {
+    assert_eq!(
+        parse("<table><tr><td>a<td>b<tr><th>c</table>"),
+        "<table><tr><td>a</td><td>b</td></tr><tr><th>c</th></tr></table>"
+    );
+    assert_eq!(
+        parse("<table><tr><td><table><tr><td>a</table>b</table>"),
+        "<table><tr><td><table><tr><td>a</td></tr></table>b</td></tr></table>"
+    );
+}
+
+#[test]
+fn headings() {
+    assert_eq!(parse("<h1>a<h2>b</h2>"), "<h1>a</h1><h2>b</h2>");
+}
@@ -0,0 +1,96 @@
+use dom::html_elements::HTMLEnum;
+use parser::errors::ParserErrorType;
+use parser::parse::{parse_html, parse_html_lenient};
+
+fn serialize(tree: &[HTMLEnum]) -> String {
+    tree.iter().map(|x| x.to_string()).collect()
+}
+
+#[test]
+fn stray_closing_tag() {
+    let (tree, errors) = parse_html_lenient("<div>a</span>b</div>");
+
+    assert_eq!(serialize(&tree), "<div>ab</div>");
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(
+        errors[0].error_type,
+        ParserErrorType::UnmatchedClosingTag
+    ));
+    assert_eq!(errors[0].column, 7);
+}
+
+#[test]
+fn unclosed_elements() {
+    let (tree, errors) = parse_html_lenient("<div><b>bold</div><span>text");
+
+    assert_eq!(serialize(&tree), "<div><b>bold</b></div><span>text</span>");
+    let unclosed: Vec<&str> = errors
+        .iter()
+        .filter_map(|x| match &x.error_type {
+            ParserErrorType::UnclosedTag { name } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(unclosed, ["b", "span"]);
+}
+
+#[test]
+fn strict_unclosed() {
+    let err = parse_html("<div><b>bold</div>").unwrap_err();
+
+    assert!(matches!(
+        err.error_type,
+        ParserErrorType::UnmatchedClosingTag
+    ));
+}
+
+#[test]
+fn malformed_attributes() {
+    let (tree, errors) = parse_html_lenient(r#"<img width="16" , height=%20 alt="x">"#);
+
+    assert!(matches!(&tree[0], HTMLEnum::Text(text) if text == ", height=%20"));
+    let HTMLEnum::Node(img) = &tree[1] else {
+        panic!("Expected a node")
+    };
+    let img = img.borrow();
+    assert_eq!(img.get_attribute(","), None);
+    assert_eq!(img.get_attribute("height"), None);
+    assert_eq!(img.get_attribute("width"), Some(Some("16".to_string())));
+    assert_eq!(img.get_attribute("alt"), Some(Some("x".to_string())));
+    assert_eq!(errors.len(), 2);
+}
+
+#[test]
+fn truncated_tag() {
+    let (tree, errors) = parse_html_lenient("<p>text</p><div class=\"a");
+
+    assert_eq!(serialize(&tree), "<p>text</p>");
+    assert!(matches!(
+        errors[0].error_type,
+        ParserErrorType::UnexpectedEOF
+    ));
+}
+
+#[test]
+fn stackoverflow() {
+    let (tree, errors) = parse_html_lenient(include_str!(".././tests/sources/stackoverflow.html"));
+
+    assert!(!tree.is_empty());
+    assert!(!errors.is_empty());
+}
+
+#[test]
+fn truncated_openers() {
+    for source in [
+        "<!", "li<!", "--><!", "<!-", "<!doc", "<", "<a", "<a href", "</", "<!--",
+    ] {
+        parse_html_lenient(source);
+    }
+
+    let (tree, errors) = parse_html_lenient("li<!");
+    assert_eq!(serialize(&tree), "li&lt;!");
+    assert!(errors.is_empty());
+
+    let (tree, _) = parse_html_lenient("<p>a</p><!doc");
+    assert_eq!(serialize(&tree), "<p>a</p>&lt;!doc");
+}
@@ -0,0 +1,234 @@
+use dom::document::HTMLDocument;
+use dom::html_elements::{HTMLEnum, HTMLNode, HTMLNodeRef};
+use parser::parse::{parse_html, ParseDocument};
+use std::panic::{self, AssertUnwindSafe};
+
+fn root(source: &str) -> HTMLNodeRef {
+    match parse_html(source).unwrap().into_iter().next() {
+        Some(HTMLEnum::Node(node)) => node,
+        other => panic!("Expected a node, got {:?}", other),
+    }
+}
+
+fn element(name: &str) -> HTMLNodeRef {
+    let node = HTMLNode::new();
+    node.set_name(name);
+    node
+}
+
+fn child(node: &HTMLNodeRef, index: usize) -> HTMLNodeRef {
+    node.borrow().rec_html_children()[index].clone()
+}
+
+fn is_child_of(node: &HTMLNodeRef, parent: &HTMLNodeRef) -> bool {
+    node.borrow().parent().is_some_and(|x| x == *parent)
+}
+
+#[test]
+fn insert() {
+    let div = root("<div><p>a</p><p>b</p></div>");
+    let b = child(&div, 1);
+
+    let span = element("span");
+    b.borrow_mut()
+        .insert_before(vec![HTMLEnum::Node(span.clone())]);
+    assert_eq!(
+        div.borrow().to_string(),
+        "<div><p>a</p><span></span><p>b</p></div>"
+    );
+    assert!(is_child_of(&span, &div));
+
+    let i = element("i");
+    b.borrow_mut().insert_after(vec![
+        HTMLEnum::Text("x".to_string()),
+        HTMLEnum::Node(i.clone()),
+    ]);
+    assert_eq!(
+        div.borrow().to_string(),
+        "<div><p>a</p><span></span><p>b</p>x<i></i></div>"
+    );
+
+    div.borrow_mut().prepend(vec![HTMLEnum::Node(i.clone())]);
+    assert_eq!(
+        div.borrow().to_string(),
+        "<div><i></i><p>a</p><span></span><p>b</p>x</div>"
+    );
+
+    // Moving a node into another parent detaches it from the previous one
+    let a = child(&div, 1);
+    a.borrow_mut().prepend(vec![HTMLEnum::Node(span.clone())]);
+    assert!(is_child_of(&span, &a));
+    assert_eq!(
+        div.borrow().to_string(),
+        "<div><i></i><p><span></span>a</p><p>b</p>x</div>"
+    );
+
+    // Appended elements link back to their parent too
+    let u = element("u");
+    div.borrow_mut()
+        .add_children(vec![HTMLEnum::Node(u.clone())]);
+    assert!(is_child_of(&u, &div));
+
+    // Without a parent, there is nowhere to insert
+    let orphan = element("b");
+    orphan
+        .borrow_mut()
+        .insert_before(vec![HTMLEnum::Node(element("u"))]);
+    assert!(orphan.borrow().parent().is_none());
+}
+
+#[test]
+fn replace_and_unwrap() {
+    let div = root("<div><p>a<b>b</b>c</p><hr></div>");
+    let p = child(&div, 0);
+    let b = child(&div, 1);
+
+    p.borrow_mut().unwrap();
+    assert_eq!(div.borrow().to_string(), "<div>a<b>b</b>c<hr/></div>");
+    assert!(is_child_of(&b, &div));
+    assert!(p.borrow().parent().is_none());
+    assert!(p.borrow().children().is_empty());
+
+    let em = element("em");
+    b.borrow_mut()
+        .replace_with(vec![HTMLEnum::Node(em.clone())]);
+    assert_eq!(div.borrow().to_string(), "<div>a<em></em>c<hr/></div>");
+    assert!(b.borrow().parent().is_none());
+    assert!(is_child_of(&em, &div));
+
+    // A node can be replaced by its own children
+    let wrapper = root("<section><article><h1>t</h1></article></section>");
+    let article = child(&wrapper, 0);
+    let h1 = child(&wrapper, 1);
+    article
+        .borrow_mut()
+        .replace_with(vec![HTMLEnum::Node(h1.clone())]);
+    assert_eq!(
+        wrapper.borrow().to_string(),
+        "<section><h1>t</h1></section>"
+    );
+    assert!(is_child_of(&h1, &wrapper));
+}
+
+#[test]
+fn insert_itself() {
+    let div = root("<div><p>a</p><p>b</p></div>");
+    let a = child(&div, 0);
+    let node = || vec![HTMLEnum::Node(a.clone())];
+
+    a.borrow_mut().insert_before(node());
+    a.borrow_mut().insert_after(node());
+    a.borrow_mut().replace_with(node());
+    assert_eq!(div.borrow().to_string(), "<div><p>a</p><p>b</p></div>");
+    assert!(is_child_of(&a, &div));
+
+    // Among other nodes, it ends up in their midst
+    a.borrow_mut().replace_with(vec![
+        HTMLEnum::Text("x".to_string()),
+        HTMLEnum::Node(a.clone()),
+        HTMLEnum::Text("y".to_string()),
+    ]);
+    assert_eq!(div.borrow().to_string(), "<div>x<p>a</p>y<p>b</p></div>");
+    assert!(is_child_of(&a, &div));
+}
+
+#[test]
+fn remove_and_set_text() {
+    let div = root("<div><p>a</p>b<p>c</p></div>");
+    let a = child(&div, 0);
+
+    assert!(div.borrow_mut().remove_child(&a));
+    assert!(!div.borrow_mut().remove_child(&a));
+    assert!(a.borrow().parent().is_none());
+    assert_eq!(div.borrow().to_string(), "<div>b<p>c</p></div>");
+
+    let c = child(&div, 0);
+    div.borrow_mut().set_text_content("x < y");
+    assert_eq!(div.borrow().to_string(), "<div>x &lt; y</div>");
+    assert!(c.borrow().parent().is_none());
+
+    div.borrow_mut().set_text_content("");
+    assert!(div.borrow().children().is_empty());
+}
+
+#[test]
+fn attributes() {
+    let p = root("<p class=a hidden title=t></p>");
+
+    assert_eq!(
+        p.borrow_mut().remove_attribute("class"),
+        Some(Some("a".to_string()))
+    );
+    assert_eq!(p.borrow_mut().remove_attribute("class"), None);
+
+    assert!(!p.borrow_mut().toggle_attribute("hidden", None));
+    assert!(p.borrow_mut().toggle_attribute("hidden", None));
+    assert!(p.borrow_mut().toggle_attribute("hidden", Some(true)));
+    assert!(!p.borrow_mut().toggle_attribute("open", Some(false)));
+    assert_eq!(p.borrow().to_string(), "<p title=\"t\" hidden></p>");
+}
+
+#[test]
+fn id_index() {
+    let document = HTMLDocument::parse("<div id=a><p id=b>x</p></div><span id=c></span>").unwrap();
+
+    let div = document.get_element_by_id("a").unwrap();
+    div.borrow_mut().remove_attribute("id");
+    assert!(document.get_element_by_id("a").is_none());
+
+    div.borrow_mut().set_text_content("gone");
+    assert!(document.get_element_by_id("b").is_none());
+
+    let span = document.get_element_by_id("c").unwrap();
+    let p = element("p");
+    p.borrow_mut()
+        .attribute("id".to_string(), Some("d".to_string()));
+    span.borrow_mut()
+        .replace_with(vec![HTMLEnum::Node(p.clone())]);
+    assert!(document.get_element_by_id("c").is_none());
+    assert!(document.get_element_by_id("d").unwrap() == p);
+}
+
+#[test]
+#[should_panic]
+fn cycles() {
+    let div = root("<div><p></p></div>");
+    let p = child(&div, 0);
+
+    p.borrow_mut().prepend(vec![HTMLEnum::Node(div.clone())]);
+}
+
+#[test]
+fn insert_out_of_range() {
+    let div = root("<div><p></p></div>");
+    let span = root("<span><b></b></span>");
+    let b = child(&span, 0);
+
+    let inserted = panic::catch_unwind(AssertUnwindSafe(|| {
+        div.borrow_mut()
+            .insert_children(2, vec![HTMLEnum::Node(b.clone())]);
+    }));
+
+    // Nothing was moved before panicking
+    assert!(inserted.is_err());
+    assert!(is_child_of(&b, &span));
+}
+
+#[test]
+fn threads() {
+    let div = root("<div></div>");
+
+    std::thread::scope(|scope| {
+        for i in 0..4 {
+            let div = div.clone();
+            scope.spawn(move || {
+                let p = element("p");
+                p.add_text(i.to_string());
+                div.add_child(p);
+            });
+        }
+    });
+
+    assert_eq!(div.rec_html_children().len(), 4);
+    assert!(div.rec_html_children().iter().all(|p| is_child_of(p, &div)));
+}
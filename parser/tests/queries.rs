@@ -0,0 +1,490 @@
+use dom::doctype::QuirksMode;
+use dom::document::HTMLDocument;
+use dom::hqueries::{HQueryable, HSelectorSet};
+use dom::html_elements::HTMLEnum;
+use parser::errors::ParserErrorType;
+use parser::parse::parse_html;
+use parser::parse::ParseDocument;
+use parser::queries::{parse_combined, parse_list, parse_simple};
+
+fn first_node(source: &str) -> dom::html_elements::HTMLNodeRef {
+    match parse_html(source).unwrap().into_iter().next() {
+        Some(HTMLEnum::Node(node)) => node,
+        other => panic!("Expected a node, got {:?}", other),
+    }
+}
+
+fn matches(selector: &str, source: &str) -> bool {
+    parse_simple(selector).unwrap().matches(&first_node(source))
+}
+
+#[test]
+fn compound() {
+    let source = r#"<div class="card big" id="main" data-x="1" hidden></div>"#;
+
+    assert!(matches(r#"div.card#main[data-x="1"]"#, source));
+    assert!(matches("DIV.big.card", source));
+    assert!(matches("*[hidden]", source));
+    assert!(matches("#main", source));
+    assert!(matches(" [data-x='1'] ", source));
+    assert!(matches("[ data-x = '1' ]", source));
+    assert!(matches("[ID=main]", source));
+
+    assert!(!matches("span.card", source));
+    assert!(!matches(".small", source));
+    assert!(!matches("#other", source));
+    assert!(!matches("[data-x='2']", source));
+    assert!(!matches("[title]", source));
+}
+
+#[test]
+fn escapes() {
+    let source = r#"<p class="a:b 1st" data-q='say "hi"'></p>"#;
+
+    assert!(matches(r".a\:b", source));
+    assert!(matches(r".\31 st", source));
+    assert!(matches(r#"[data-q="say \"hi\""]"#, source));
+}
+
+#[test]
+fn errors() {
+    let err = parse_simple("div.card[data-x=\"1\"").unwrap_err();
+    assert!(matches!(err.error_type, ParserErrorType::UnexpectedEOF));
+    assert_eq!(err.column, 20);
+
+    let err = parse_simple("div..card").unwrap_err();
+    assert!(matches!(err.error_type, ParserErrorType::InvalidName));
+    assert_eq!((err.char, err.column), (4, 5));
+
+    let err = parse_simple("a[href!x]").unwrap_err();
+    assert!(matches!(
+        err.error_type,
+        ParserErrorType::UnexpectedCharacter { .. }
+    ));
+    assert_eq!(err.char, 6);
+
+    let err = parse_simple("div%").unwrap_err();
+    assert_eq!(err.char, 3);
+    assert_eq!(
+        err.to_string(),
+        "unexpected character, expected '.' or '#' or '[' or ':' at line 1, column 4\n1 | div%\n  |    ^"
+    );
+
+    assert!(parse_simple("").is_err());
+    assert!(parse_simple("[1=x]").is_err());
+    assert!(parse_simple("[x=1]").is_err());
+}
+
+fn select(selector: &str, source: &str) -> Vec<String> {
+    let query = parse_combined(selector).unwrap();
+
+    query
+        .select(&first_node(source))
+        .iter()
+        .map(|x| x.borrow().text_content())
+        .collect()
+}
+
+#[test]
+fn combinators() {
+    let source = "<div><ul class=menu><li><a>1</a></li><li><p><a>2</a></p></li></ul><a>3</a>\
+        <ol><li><a>4</a></li></ol></div>";
+
+    assert_eq!(select("ul > li a", source), vec!["1", "2"]);
+    assert_eq!(select("ul>li>a", source), vec!["1"]);
+    assert_eq!(select("li a", source), vec!["1", "2", "4"]);
+    assert_eq!(select("div > a", source), vec!["3"]);
+    assert_eq!(select(".menu p > a", source), vec!["2"]);
+    assert_eq!(select("  div   a  ", source), vec!["1", "2", "3", "4"]);
+    assert!(select("ol > a", source).is_empty());
+}
+
+#[test]
+fn combinator_errors() {
+    let err = parse_combined("ul >").unwrap_err();
+    assert!(matches!(err.error_type, ParserErrorType::UnexpectedEOF));
+
+    let err = parse_combined("ul > > li").unwrap_err();
+    assert_eq!(err.char, 5);
+
+    let err = parse_combined("ul li%").unwrap_err();
+    assert_eq!(err.char, 5);
+}
+
+#[test]
+fn sibling_combinators() {
+    let source = "<div><h2>1</h2><p>2</p>text<p>3</p><h3>4</h3><p>5</p><section><h2>6</h2><p>7</p></section></div>";
+
+    assert_eq!(select("h2 + p", source), vec!["2", "7"]);
+    assert_eq!(select("h2 ~ p", source), vec!["2", "3", "5", "7"]);
+    assert_eq!(select("p + p", source), vec!["3"]);
+    assert_eq!(select("h3+p", source), vec!["5"]);
+    assert_eq!(select("h2 ~ h3 ~ p", source), vec!["5"]);
+    assert_eq!(select("div > h2 + p", source), vec!["2"]);
+    assert_eq!(select("h3 ~ section p", source), vec!["7"]);
+    assert!(select("p ~ h2", source).is_empty());
+}
+
+#[test]
+fn attribute_operators() {
+    let link = r#"<a href="https://example.com/docs.PDF" rel="noopener  external" lang="en-US" data-e=""></a>"#;
+
+    assert!(matches(r#"a[href^="https://"]"#, link));
+    assert!(matches(r#"a[href$=".PDF"]"#, link));
+    assert!(matches(r#"a[href$=".pdf" i]"#, link));
+    assert!(!matches(r#"a[href$=".pdf"]"#, link));
+    assert!(!matches(r#"a[href$=".pdf" s]"#, link));
+    assert!(matches(r#"[href*=example]"#, link));
+    assert!(matches(r#"[rel~=external]"#, link));
+    assert!(!matches(r#"[rel~=extern]"#, link));
+    assert!(!matches(r#"[rel~="noopener external"]"#, link));
+    assert!(matches(r#"[lang|=en]"#, link));
+    assert!(matches(r#"[lang|=en-us i]"#, link));
+    assert!(!matches(r#"[lang|=e]"#, link));
+    assert!(matches(r#"[data-e=""]"#, link));
+    assert!(!matches(r#"[data-e^=""]"#, link));
+    assert!(!matches(r#"[data-e*=""]"#, link));
+    assert!(!matches(r#"[title^=x]"#, link));
+
+    let stylesheet = r#"<link rel="preload stylesheet">"#;
+    assert!(matches(r#"link[rel~="stylesheet"]"#, stylesheet));
+}
+
+#[test]
+fn attribute_operator_errors() {
+    let err = parse_simple("[href^x]").unwrap_err();
+    assert_eq!(err.char, 6);
+
+    let err = parse_simple("[href=x y]").unwrap_err();
+    assert_eq!(err.char, 8);
+
+    assert!(parse_simple("[href!=x]").is_err());
+    assert!(parse_simple("[href^=]").is_err());
+}
+
+#[test]
+fn structural_pseudo_classes() {
+    let list =
+        "<ul><li>1</li><!--c--><li class=x>2</li>text<li>3</li><li class=x>4</li><li>5</li></ul>";
+
+    assert_eq!(select("li:first-child", list), vec!["1"]);
+    assert_eq!(select("li:last-child", list), vec!["5"]);
+    assert_eq!(select("li:nth-child(2)", list), vec!["2"]);
+    assert_eq!(select("li:nth-child(odd)", list), vec!["1", "3", "5"]);
+    assert_eq!(select("li:NTH-CHILD( Even )", list), vec!["2", "4"]);
+    assert_eq!(select("li:nth-child(2n+1)", list), vec!["1", "3", "5"]);
+    assert_eq!(select("li:nth-child(-n + 2)", list), vec!["1", "2"]);
+    assert_eq!(select("li:nth-child(n+4)", list), vec!["4", "5"]);
+    assert_eq!(select("li:nth-child(3n - 1)", list), vec!["2", "5"]);
+    assert_eq!(select("li:nth-last-child(2)", list), vec!["4"]);
+    assert_eq!(select("li:nth-child(2 of .x)", list), vec!["4"]);
+    assert_eq!(select("li:nth-last-child(1 of .x)", list), vec!["4"]);
+    assert_eq!(select("li:nth-child(2 OF .x)", list), vec!["4"]);
+    assert_eq!(
+        select("li:nth-child(odd of .y)", list),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn type_pseudo_classes() {
+    let source = "<div><h2>1</h2><p>2</p><p>3</p><span>4</span><p>5</p></div>";
+
+    assert_eq!(select(":first-of-type", source), vec!["1", "2", "4"]);
+    assert_eq!(select("p:last-of-type", source), vec!["5"]);
+    assert_eq!(select(":only-of-type", source), vec!["1", "4"]);
+    assert_eq!(select("p:nth-of-type(2)", source), vec!["3"]);
+    assert_eq!(select("p:nth-last-of-type(odd)", source), vec!["2", "5"]);
+    assert_eq!(select("span:only-child", source), Vec::<String>::new());
+}
+
+#[test]
+fn empty_and_root() {
+    let source = "<div><p></p><p><!--c--></p><p> </p><p><b></b></p></div>";
+
+    let query = parse_combined("p:empty").unwrap();
+    let root = first_node(source);
+    assert_eq!(query.select(&root).len(), 2);
+
+    assert!(matches(":root", source));
+    assert!(matches("div:only-child:first-child", source));
+    assert!(parse_combined(":root p").unwrap().select(&root).len() == 4);
+}
+
+#[test]
+fn pseudo_class_errors() {
+    let err = parse_simple("li:nth-child(2n+)").unwrap_err();
+    assert!(matches!(err.error_type, ParserErrorType::InvalidValue));
+    assert_eq!(err.char, 13);
+
+    let err = parse_simple("li:hover").unwrap_err();
+    assert!(matches!(
+        err.error_type,
+        ParserErrorType::UnknownPseudoClass { ref name } if name == "hover"
+    ));
+    assert_eq!(err.char, 3);
+    assert!(err.to_string().starts_with("unknown pseudo-class :hover"));
+
+    assert!(parse_simple("li:nth-child(2").is_err());
+    assert!(parse_simple("li:nth-child(2 of)").is_err());
+    assert!(parse_simple("li:nth-of-type(2 of p)").is_err());
+    assert!(parse_simple("li:nth-child(oddish)").is_err());
+    assert!(parse_simple("li:first-child()").is_err());
+}
+
+#[test]
+fn logical_pseudo_classes() {
+    let source = "<div><p class=a>1</p><p class=b>2</p><span class=a>3</span><p>4</p></div>";
+
+    assert_eq!(select("p:not(.a)", source), vec!["2", "4"]);
+    assert_eq!(select("p:not(.a, .b)", source), vec!["4"]);
+    assert_eq!(select(":is(p, span).a", source), vec!["1", "3"]);
+    assert_eq!(select(":where(span, .b)", source), vec!["2", "3"]);
+    assert_eq!(select("div :is(p + p)", source), vec!["2"]);
+    assert_eq!(
+        select(":not(:first-child):not(:last-child)", source),
+        vec!["2", "3"]
+    );
+    assert_eq!(select("p:nth-child(1 of .a, span)", source), vec!["1"]);
+}
+
+#[test]
+fn has() {
+    let source = "<div><section id=1><img alt=x></section>\
+        <section id=2><figure><img></figure></section>\
+        <section id=3><p>x</p></section>\
+        <h2>a</h2><p>b</p><h2>c</h2><ul><li>d</li></ul></div>";
+
+    let ids = |selector: &str| -> Vec<String> {
+        parse_combined(selector)
+            .unwrap()
+            .select(&first_node(source))
+            .iter()
+            .map(|x| x.borrow().get_attribute("id").flatten().unwrap_or_default())
+            .collect()
+    };
+
+    assert_eq!(ids("section:has(img:not([alt]))"), vec!["2"]);
+    assert_eq!(ids("section:has(img)"), vec!["1", "2"]);
+    assert_eq!(ids("section:has(> img)"), vec!["1"]);
+    assert_eq!(ids("section:has(figure img, p)"), vec!["2", "3"]);
+    assert_eq!(ids("section:has(> figure > img)"), vec!["2"]);
+    assert_eq!(ids("div:has(div img)"), Vec::<String>::new());
+
+    assert_eq!(select("h2:has(+ p)", source), vec!["a"]);
+    assert_eq!(select("h2:has(~ ul li)", source), vec!["a", "c"]);
+    assert_eq!(select("h2:has(~ p)", source), vec!["a"]);
+    assert_eq!(select("section:not(:has(img))", source), vec!["x"]);
+}
+
+#[test]
+fn logical_errors() {
+    assert!(parse_simple("p:not()").is_err());
+    assert!(parse_simple("p:not(.a,)").is_err());
+    assert!(parse_simple("p:is(.a").is_err());
+    assert!(parse_simple("p:has(> )").is_err());
+
+    let err = parse_simple("p:not(.a .b").unwrap_err();
+    assert!(matches!(err.error_type, ParserErrorType::UnexpectedEOF));
+}
+
+#[test]
+fn query_selector() {
+    let root = first_node(
+        "<div><h1>1</h1><section><h2>2</h2><p class=x>3</p></section><h2 class=x>4</h2></div>",
+    );
+
+    let list = parse_list("h2, .x, section > h2").unwrap();
+    let all: Vec<String> = root
+        .query_selector_all(&list)
+        .iter()
+        .map(|x| x.borrow().text_content())
+        .collect();
+    assert_eq!(all, vec!["2", "3", "4"]);
+
+    let first = root.query_selector(&parse_list(".x, h1").unwrap()).unwrap();
+    assert_eq!(first.borrow().text_content(), "1");
+
+    let section = root
+        .query_selector(&parse_simple("section").unwrap())
+        .unwrap();
+    let inner = section
+        .borrow()
+        .query_selector_all(&parse_list("h2, p").unwrap());
+    assert_eq!(inner.len(), 2);
+
+    // The element itself is not part of the search
+    assert!(section
+        .query_selector(&parse_simple("section").unwrap())
+        .is_none());
+    assert!(root
+        .query_selector(&parse_combined("div > h1").unwrap())
+        .is_some());
+}
+
+#[test]
+fn document_query_selector() {
+    let document = HTMLDocument::parse("<title>t</title><ul><li>a<li>b</ul>").unwrap();
+
+    let items = document.query_selector_all(&parse_list("li, html, head > title").unwrap());
+    let names: Vec<String> = items
+        .iter()
+        .map(|x| x.borrow().name().to_string())
+        .collect();
+    assert_eq!(names, vec!["html", "title", "li", "li"]);
+}
+
+#[test]
+fn list_errors() {
+    assert!(parse_list("h1,").is_err());
+    assert!(parse_list(", h1").is_err());
+    assert!(parse_list("h1)").is_err());
+    assert!(parse_list(" h1 , h2 ").is_ok());
+}
+
+#[test]
+fn class_and_id_semantics() {
+    let source = "<div class=\"a\tb\nc\r\n d\" ID=Main></div>";
+
+    assert!(matches(".a.b.c.d", source));
+    assert!(matches("#Main", source));
+    assert!(!matches("#main", source));
+    assert!(!matches(".A", source));
+    assert!(!matches("div.a:not(.b)", source));
+
+    assert!(!matches("#x", "<div id></div>"));
+    assert!(!matches(".x", "<div></div>"));
+    assert!(matches("div", "<div></div>"));
+}
+
+#[test]
+fn quirks_mode_matching() {
+    let source = "<p class=Note id=First>x</p>";
+    let query = parse_list(".note#first").unwrap();
+
+    let standards = HTMLDocument::parse(&format!("<!DOCTYPE html>{}", source)).unwrap();
+    assert!(standards.query_selector(&query).is_none());
+    assert!(standards
+        .query_selector(&parse_list(".Note#First").unwrap())
+        .is_some());
+
+    let quirks = HTMLDocument::parse(source).unwrap();
+    assert!(quirks.query_selector(&query).is_some());
+
+    // Attribute values and tag names are not affected
+    assert!(quirks
+        .query_selector(&parse_list("[class=note]").unwrap())
+        .is_none());
+}
+
+fn specificity(selector: &str) -> (u32, u32, u32) {
+    let specificity = parse_list(selector).unwrap().specificity();
+    (specificity.ids, specificity.classes, specificity.types)
+}
+
+#[test]
+fn specificities() {
+    assert_eq!(specificity("*"), (0, 0, 0));
+    assert_eq!(specificity("li"), (0, 0, 1));
+    assert_eq!(specificity("ul li"), (0, 0, 2));
+    assert_eq!(specificity("ul ol+li"), (0, 0, 3));
+    assert_eq!(specificity("h1 + *[rel=up]"), (0, 1, 1));
+    assert_eq!(specificity("ul ol li.red"), (0, 1, 3));
+    assert_eq!(specificity("li.red.level"), (0, 2, 1));
+    assert_eq!(specificity("#x34y"), (1, 0, 0));
+    assert_eq!(specificity("#s12:not(FOO)"), (1, 0, 1));
+    assert_eq!(specificity(".foo :is(.bar, #baz)"), (1, 1, 0));
+    assert_eq!(specificity(":where(#a, .b) p"), (0, 0, 1));
+    assert_eq!(specificity("li:nth-child(2n of .x, #y)"), (1, 1, 1));
+    assert_eq!(specificity("li:first-child:empty"), (0, 2, 1));
+    assert_eq!(specificity("section:has(> img, #hero)"), (1, 0, 1));
+    assert_eq!(specificity("p, #a, .b"), (1, 0, 0));
+}
+
+#[test]
+fn specificity_ordering() {
+    let id = parse_combined("#a").unwrap().specificity();
+    let classes = parse_combined(".a.b.c.d.e.f.g.h.i.j.k")
+        .unwrap()
+        .specificity();
+    let class = parse_combined("div.a").unwrap().specificity();
+    let types = parse_combined("html body div p").unwrap().specificity();
+
+    assert!(id > classes);
+    assert!(classes > class);
+    assert!(class > types);
+    assert_eq!(id.to_string(), "(1, 0, 0)");
+
+    let list = parse_list("p, .note, #main").unwrap();
+    let source = "<div><p class=note>x</p><p>y</p><span>z</span></div>";
+    let root = first_node(source);
+    let nodes = root.borrow().rec_html_children();
+
+    assert_eq!(
+        list.specificity_for(&nodes[0], QuirksMode::NoQuirks)
+            .map(|x| x.to_string()),
+        Some("(0, 1, 0)".to_string())
+    );
+    assert_eq!(
+        list.specificity_for(&nodes[1], QuirksMode::NoQuirks)
+            .map(|x| x.to_string()),
+        Some("(0, 0, 1)".to_string())
+    );
+    assert!(list
+        .specificity_for(&nodes[2], QuirksMode::NoQuirks)
+        .is_none());
+
+    // Class names are matched case-insensitively in quirks mode
+    let list = parse_list(".NOTE").unwrap();
+    assert!(list
+        .specificity_for(&nodes[0], QuirksMode::NoQuirks)
+        .is_none());
+    assert!(list
+        .specificity_for(&nodes[0], QuirksMode::Quirks)
+        .is_some());
+}
+
+#[test]
+fn selector_set() {
+    let rules = [
+        "p",
+        ".note",
+        "#main > p, div.note",
+        "*",
+        "[data-x]",
+        ".missing",
+        "p:not(.note)",
+        "BODY > DIV#Main",
+    ];
+    let document = HTMLDocument::parse(
+        "<!DOCTYPE html><div id=main class=\"note box\"><p class=note data-x>x</p><p>y</p></div><div class=NOTE></div>",
+    )
+    .unwrap();
+
+    let mut set = HSelectorSet::new();
+    for (index, rule) in rules.iter().enumerate() {
+        assert_eq!(set.add(parse_list(rule).unwrap()), index);
+    }
+    assert_eq!(set.len(), rules.len());
+
+    let selected = set.select_all(&document);
+    for (rule, nodes) in rules.iter().zip(selected.iter()) {
+        let expected = document.query_selector_all(&parse_list(rule).unwrap());
+        assert_eq!(nodes.len(), expected.len(), "{}", rule);
+        assert!(nodes.iter().zip(expected.iter()).all(|(x, y)| x == y));
+    }
+
+    assert_eq!(selected[2].len(), 3);
+    assert!(selected[5].is_empty());
+    assert!(selected[7].is_empty());
+
+    let p = document
+        .query_selector(&parse_simple("p").unwrap())
+        .unwrap();
+    assert_eq!(set.matching(&p), vec![0, 1, 2, 3, 4]);
+    assert_eq!(set.matching_in(&p, QuirksMode::Quirks), vec![0, 1, 2, 3, 4]);
+
+    let last = document.body().borrow().rec_html_children().pop().unwrap();
+    assert_eq!(set.matching(&last), vec![3]);
+    assert_eq!(set.matching_in(&last, QuirksMode::Quirks), vec![1, 2, 3]);
+}
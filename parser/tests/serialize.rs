@@ -0,0 +1,64 @@
+use dom::html_elements::HTMLEnum;
+use parser::parse::parse_html;
+
+/// Compares two trees, ignoring attribute order.
+/// Only the attributes used in these tests are compared
+fn same_tree(left: &[HTMLEnum], right: &[HTMLEnum]) -> bool {
+    left.len() == right.len()
+        && left.iter().zip(right).all(|pair| match pair {
+            (HTMLEnum::Text(a), HTMLEnum::Text(b)) => a == b,
+            (HTMLEnum::Comment(a), HTMLEnum::Comment(b)) => a == b,
+            (HTMLEnum::Node(a), HTMLEnum::Node(b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                a.name() == b.name()
+                    && ["title", "class", "hidden", "href"]
+                        .iter()
+                        .all(|attr| a.get_attribute(attr) == b.get_attribute(attr))
+                    && same_tree(&a.children(), &b.children())
+            }
+            _ => false,
+        })
+}
+
+fn round_trip(source: &str) -> String {
+    let tree = parse_html(source).unwrap();
+    let serialized: String = tree.iter().map(|x| x.to_string()).collect();
+    let reparsed = parse_html(&serialized).unwrap();
+
+    assert!(same_tree(&tree, &reparsed), "{serialized}");
+    serialized
+}
+
+#[test]
+fn escaped_text() {
+    assert_eq!(
+        round_trip("<p>a &lt; b &amp;&amp; c &gt; d</p>"),
+        "<p>a &lt; b &amp;&amp; c &gt; d</p>"
+    );
+}
+
+#[test]
+fn escaped_attributes() {
+    assert_eq!(
+        round_trip(r#"<a title='say "hi" &amp; <leave>'>x</a>"#),
+        r#"<a title="say &quot;hi&quot; &amp; &lt;leave&gt;">x</a>"#
+    );
+}
+
+#[test]
+fn valueless_attributes() {
+    assert_eq!(round_trip("<div hidden></div>"), "<div hidden></div>");
+}
+
+#[test]
+fn raw_text() {
+    let source = "<script>if (a < b && c) { x = '</p>'; }</script><style>a > b { }</style>";
+    assert_eq!(round_trip(source), source);
+}
+
+#[test]
+fn mixed_document() {
+    round_trip(
+        "<ul class=\"list\"><li>one &nbsp;&copy;</li><!-- a < b --><li><a href=\"?a=1&amp;b=2\">two</a></li></ul>",
+    );
+}
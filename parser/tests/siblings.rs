@@ -0,0 +1,43 @@
+use dom::html_elements::{HTMLEnum, HTMLNodeRef};
+use parser::parse::parse_html;
+
+/// Returns the root, which must be kept alive for the children to reach their parent, and its descendants
+fn children(source: &str) -> (HTMLNodeRef, Vec<HTMLNodeRef>) {
+    match parse_html(source).unwrap().into_iter().next() {
+        Some(HTMLEnum::Node(node)) => {
+            let children = node.borrow().rec_html_children();
+            (node, children)
+        }
+        other => panic!("Expected a node, got {:?}", other),
+    }
+}
+
+#[test]
+fn navigation() {
+    let (_root, nodes) = children("<div><h2>a</h2> text <!--c--><p>b</p><p>c</p></div>");
+    let (h2, p1, p2) = (&nodes[0], &nodes[1], &nodes[2]);
+
+    assert_eq!(h2.borrow().index_in_parent(), Some(0));
+    assert_eq!(p1.borrow().index_in_parent(), Some(3));
+
+    assert!(matches!(h2.borrow().next_sibling(), Some(HTMLEnum::Text(x)) if x == " text "));
+    assert!(matches!(p1.borrow().previous_sibling(), Some(HTMLEnum::Comment(x)) if x == "c"));
+    assert!(h2.borrow().previous_sibling().is_none());
+    assert!(p2.borrow().next_sibling().is_none());
+
+    let next = h2.borrow().next_element_sibling().unwrap();
+    assert!(next == *p1);
+
+    let previous = p1.borrow().previous_element_sibling().unwrap();
+    assert!(previous == *h2);
+    assert!(p2.borrow().next_element_sibling().is_none());
+}
+
+#[test]
+fn orphans() {
+    let node = dom::html_elements::HTMLNode::new();
+
+    assert_eq!(node.borrow().index_in_parent(), None);
+    assert!(node.borrow().next_sibling().is_none());
+    assert!(node.borrow().previous_element_sibling().is_none());
+}
@@ -39,3 +39,14 @@ fn stackoverflow() -> Result<(), ParserError> {
 fn reflection() -> Result<(), ParserError> {
     parse_html(include_str!(".././tests/sources/reflection.html")).map(|_| ())
 }
+
+#[test]
+fn after_comment() {
+    let tree = parse_html("<!-- a -->b<p>c</p>").unwrap();
+    assert_eq!(tree.len(), 3)
+}
+
+#[test]
+fn closer_case() {
+    assert!(parse_html("<div>Hello</DIV >").is_ok())
+}
@@ -12,8 +12,7 @@ fn main() {
                     }
                 }
                 Err(n) => {
-                    println!("{:#?}", n);
-                    println!("Source : {}", &str[n.char..n.char + 10])
+                    println!("{}", n);
                 }
             },
             Err(why) => {